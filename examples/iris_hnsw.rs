@@ -1,14 +1,19 @@
 use std::io::Read;
 
 use tomato::backend::{AnnBackend, HnswBackend, HnswParams};
-use tomato::pipeline::{run_pipeline, DensitySpec, GraphSpec, PipelineParams};
+use tomato::hierarchy::MergeTree;
+use tomato::metrics::{adjusted_rand_index, fowlkes_mallows, normalized_mutual_info, silhouette, v_measure, NoiseHandling};
+use tomato::pipeline::{build_graph, estimate_density, DensitySpec, GraphSpec};
+use tomato::selection::{select_tau, TauCriterion};
 use tomato::stats::zscore_in_place;
-use tomato::tomato::TomatoParams;
 
 const IRIS_CSV_URL: &str =
     "https://gist.githubusercontent.com/netj/8836201/raw/6f9306ad21398ea43cba4f7d537619d0e07d5ae3/iris.csv";
 
-fn load_iris_points() -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
+type Iris = (Vec<Vec<f64>>, Vec<usize>);
+
+// Points and species, the species numbered in order of first appearance.
+fn load_iris() -> Result<Iris, Box<dyn std::error::Error>> {
     let mut resp = reqwest::blocking::get(IRIS_CSV_URL)?;
     let mut body = String::new();
     resp.read_to_string(&mut body)?;
@@ -16,6 +21,8 @@ fn load_iris_points() -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
     let mut rdr = csv::Reader::from_reader(body.as_bytes());
 
     let mut pts: Vec<Vec<f64>> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut species: Vec<usize> = Vec::new();
     for row in rdr.records() {
         let r = row?;
         let sl: f64 = r[0].parse()?;
@@ -23,12 +30,21 @@ fn load_iris_points() -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
         let pl: f64 = r[2].parse()?;
         let pw: f64 = r[3].parse()?;
         pts.push(vec![sl, sw, pl, pw]);
+
+        let id = match names.iter().position(|s| s == &r[4]) {
+            Some(id) => id,
+            None => {
+                names.push(r[4].to_string());
+                names.len() - 1
+            }
+        };
+        species.push(id);
     }
-    Ok(pts)
+    Ok((pts, species))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut points = load_iris_points()?;
+    let (mut points, species) = load_iris()?;
     zscore_in_place(&mut points);
 
     let backend = HnswBackend::new(points, HnswParams {
@@ -47,36 +63,51 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let k_rips = 50usize;
     let k_kde = 50usize;
 
-    let tau = 0.15;
+    let graph = build_graph(&backend, GraphSpec::RipsFromKnnApprox {
+        k: k_rips,
+        radius2,
+        symmetrize: true,
+    })?;
+    let density = estimate_density(&backend, DensitySpec::KdeGaussianKnn {
+        k: k_kde,
+        bandwidth2,
+    })?;
 
-    let params = PipelineParams {
-        graph: GraphSpec::RipsFromKnnApprox {
-            k: k_rips,
-            radius2,
-            symmetrize: true,
-        },
-        density: DensitySpec::KdeGaussianKnn {
-            k: k_kde,
-            bandwidth2,
-        },
-        tomato: TomatoParams { tau },
-    };
+    // Two pass workflow: build the hierarchy with tau = infinity, read tau off the diagram
 
-    let out = run_pipeline(&backend, params)?;
+    let tree = MergeTree::new(&graph, &density)?;
+    let selection = select_tau(&tree.diagram(), TauCriterion::LargestGap)?;
+    let out = tree.result_at(selection.tau)?;
 
     println!("n {}", backend.len());
-    println!("modes {}", out.tomato.modes.len());
+    println!("{}", selection);
+    println!("modes {}", out.modes.len());
 
     let mut counts = std::collections::HashMap::<usize, usize>::new();
-    for &m in &out.tomato.cluster_of {
+    for &m in &out.cluster_of {
         *counts.entry(m).or_insert(0) += 1;
     }
     let mut clusters: Vec<(usize, usize)> = counts.into_iter().collect();
-    clusters.sort_by(|a, b| b.1.cmp(&a.1));
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.1));
 
     for (mode, size) in clusters {
         println!("mode {} size {}", mode, size);
     }
 
+    // Agreement with the species column, and silhouette in the scaled feature space
+
+    let noise = NoiseHandling::Singletons;
+    println!("ari {:.4}", adjusted_rand_index(&species, &out.cluster_of, noise)?);
+    println!("nmi {:.4}", normalized_mutual_info(&species, &out.cluster_of, noise)?);
+    println!("fowlkes mallows {:.4}", fowlkes_mallows(&species, &out.cluster_of, noise)?);
+    let v = v_measure(&species, &out.cluster_of, noise)?;
+    println!(
+        "homogeneity {:.4} completeness {:.4} v measure {:.4}",
+        v.homogeneity, v.completeness, v.v_measure
+    );
+    if out.modes.len() >= 2 {
+        println!("silhouette {:.4}", silhouette(&backend, &out.cluster_of)?);
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -1,17 +1,30 @@
 #![forbid(unsafe_code)]
 
-use crate::backend::AnnBackend;
+use crate::backend::metric::{Euclidean, Metric};
+use crate::backend::{validate_query, AnnBackend};
+use crate::tomato::TomatoError;
 
 #[derive(Debug, Clone)]
-pub struct BruteBackend {
+pub struct BruteBackend<M: Metric = Euclidean> {
     points: Vec<Vec<f64>>,
     dim: usize,
+    metric: M,
 }
 
-impl BruteBackend {
+impl BruteBackend<Euclidean> {
     pub fn new(points: Vec<Vec<f64>>) -> Result<Self, String> {
+        Self::with_metric(points, Euclidean)
+    }
+}
+
+impl<M: Metric> BruteBackend<M> {
+    pub fn with_metric(points: Vec<Vec<f64>>, metric: M) -> Result<Self, String> {
         if points.is_empty() {
-            return Ok(Self { points, dim: 0 });
+            return Ok(Self {
+                points,
+                dim: 0,
+                metric,
+            });
         }
         let dim = points[0].len();
         for (i, p) in points.iter().enumerate() {
@@ -24,23 +37,27 @@ impl BruteBackend {
                 }
             }
         }
-        Ok(Self { points, dim })
+        metric.validate_dim(dim)?;
+        Ok(Self { points, dim, metric })
+    }
+
+    #[inline]
+    pub fn metric(&self) -> &M {
+        &self.metric
     }
 
     #[inline]
     fn dist2(&self, a: usize, b: usize) -> f64 {
-        let pa = &self.points[a];
-        let pb = &self.points[b];
-        let mut s = 0.0;
-        for j in 0..self.dim {
-            let d = pa[j] - pb[j];
-            s += d * d;
-        }
-        s
+        self.metric.dist2(&self.points[a], &self.points[b])
+    }
+
+    #[inline]
+    fn dist2_point(&self, a: usize, point: &[f64]) -> f64 {
+        self.metric.dist2(&self.points[a], point)
     }
 }
 
-impl AnnBackend for BruteBackend {
+impl<M: Metric> AnnBackend for BruteBackend<M> {
     fn dim(&self) -> usize {
         self.dim
     }
@@ -63,4 +80,43 @@ impl AnnBackend for BruteBackend {
         buf.truncate(kk);
         buf
     }
+
+    fn knn_point_dist2(&self, point: &[f64], k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        validate_query(point, self.dim)?;
+        let n = self.len();
+        let kk = k.min(n);
+        let mut buf: Vec<(usize, f64)> = Vec::with_capacity(n);
+        for j in 0..n {
+            buf.push((j, self.dist2_point(j, point)));
+        }
+        buf.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        buf.truncate(kk);
+        Ok(buf)
+    }
+
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        let mut out: Vec<(usize, f64)> = Vec::new();
+        for j in 0..self.len() {
+            if j == query_index {
+                continue;
+            }
+            let d2 = self.dist2(query_index, j);
+            if d2 <= radius2 {
+                out.push((j, d2));
+            }
+        }
+        out
+    }
+
+    fn range_point_dist2(&self, point: &[f64], radius2: f64) -> Result<Vec<(usize, f64)>, TomatoError> {
+        validate_query(point, self.dim)?;
+        let mut out: Vec<(usize, f64)> = Vec::new();
+        for j in 0..self.len() {
+            let d2 = self.dist2_point(j, point);
+            if d2 <= radius2 {
+                out.push((j, d2));
+            }
+        }
+        Ok(out)
+    }
 }
\ No newline at end of file
@@ -1,10 +1,21 @@
 #![forbid(unsafe_code)]
 
-use crate::backend::AnnBackend;
-use hnsw_rs::prelude::DistL2;
+use crate::backend::metric::{Euclidean, Metric};
+use crate::backend::{validate_query, AnnBackend};
+use crate::tomato::TomatoError;
+use hnsw_rs::api::AnnT;
+use hnsw_rs::hnswio::HnswIo;
+use hnsw_rs::prelude::Distance;
 use hnsw_rs::hnsw::Hnsw;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SIDECAR_MAGIC: &[u8; 8] = b"TOMATOHN";
+const SIDECAR_VERSION: u32 = 1;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct HnswParams {
     pub max_nb_connection: usize,
     pub ef_construction: usize,
@@ -25,28 +36,46 @@ impl Default for HnswParams {
     }
 }
 
-pub struct HnswBackend {
+/// Adapts a `Metric` to the distance trait of hnsw_rs, which works on unsquared f32 distances.
+#[derive(Debug, Clone, Default)]
+pub struct MetricDist<M: Metric>(pub M);
+
+impl<M: Metric> Distance<f32> for MetricDist<M> {
+    fn eval(&self, va: &[f32], vb: &[f32]) -> f32 {
+        self.0.dist2_f32(va, vb).sqrt()
+    }
+}
+
+/// Approximate kNN through hnsw_rs. `'a` is `'static` for a built index and the borrow of the
+/// `HnswIndexFile` for a loaded one.
+pub struct HnswBackend<'a, M: Metric = Euclidean> {
     points: Vec<Vec<f32>>,
     dim: usize,
-    hnsw: Hnsw<'static, f32, DistL2>,
-    ef_search: usize,
+    hnsw: Hnsw<'a, f32, MetricDist<M>>,
+    params: HnswParams,
 }
 
-impl HnswBackend {
+impl HnswBackend<'_, Euclidean> {
     pub fn new(points_f64: Vec<Vec<f64>>, params: HnswParams) -> Result<Self, String> {
+        Self::with_metric(points_f64, params, Euclidean)
+    }
+}
+
+impl<M: Metric> HnswBackend<'_, M> {
+    pub fn with_metric(points_f64: Vec<Vec<f64>>, params: HnswParams, metric: M) -> Result<Self, String> {
         if points_f64.is_empty() {
-            let hnsw = Hnsw::<f32, DistL2>::new(
+            let hnsw = Hnsw::<f32, MetricDist<M>>::new(
                 params.max_nb_connection,
                 0,
                 params.max_layer,
                 params.ef_construction,
-                DistL2 {},
+                MetricDist(metric),
             );
             return Ok(Self {
                 points: vec![],
                 dim: 0,
                 hnsw,
-                ef_search: params.ef_search,
+                params,
             });
         }
 
@@ -61,6 +90,7 @@ impl HnswBackend {
                 }
             }
         }
+        metric.validate_dim(dim)?;
 
         let mut points: Vec<Vec<f32>> = Vec::with_capacity(points_f64.len());
         for p in points_f64 {
@@ -72,24 +102,24 @@ impl HnswBackend {
         }
 
         let n = points.len();
-        let mut hnsw = Hnsw::<f32, DistL2>::new(
+        let mut hnsw = Hnsw::<f32, MetricDist<M>>::new(
             params.max_nb_connection,
             n,
             params.max_layer,
             params.ef_construction,
-            DistL2 {},
+            MetricDist(metric),
         );
 
         if params.use_parallel_insert && n >= 2000 {
             let mut datas: Vec<(&[f32], usize)> = Vec::with_capacity(n);
-            for i in 0..n {
-                datas.push((&points[i], i));
+            for (i, p) in points.iter().enumerate() {
+                datas.push((p.as_slice(), i));
             }
             hnsw.parallel_insert_slice(&datas);
             hnsw.set_searching_mode(true);
         } else {
-            for i in 0..n {
-                hnsw.insert_slice((&points[i], i));
+            for (i, p) in points.iter().enumerate() {
+                hnsw.insert_slice((p.as_slice(), i));
             }
         }
 
@@ -97,12 +127,190 @@ impl HnswBackend {
             points,
             dim,
             hnsw,
-            ef_search: params.ef_search,
+            params,
         })
     }
 }
 
-impl AnnBackend for HnswBackend {
+// Splits `dir/name` into the directory and basename that hnsw_rs expects.
+fn split_path(path: &Path) -> Result<(PathBuf, String), String> {
+    let name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| format!("invalid index path {}", path.display()))?
+        .to_string();
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok((dir, name))
+}
+
+fn sidecar_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.tomato", name))
+}
+
+fn read_u64(bytes: &[u8], at: &mut usize) -> Result<u64, String> {
+    let end = *at + 8;
+    let b = bytes.get(*at..end).ok_or("truncated index sidecar")?;
+    *at = end;
+    Ok(u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+impl<M: Metric> HnswBackend<'_, M> {
+    #[inline]
+    pub fn params(&self) -> &HnswParams {
+        &self.params
+    }
+
+    /// Writes the index to `path.hnsw.graph` and `path.hnsw.data`, and the params and f32 point
+    /// store to `path.tomato`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let (dir, name) = split_path(path.as_ref())?;
+
+        if !self.points.is_empty() {
+            let dumped = self
+                .hnsw
+                .file_dump(&dir, &name)
+                .map_err(|e| format!("hnsw dump failed: {}", e))?;
+            if dumped != name {
+                return Err(format!("hnsw dump was written as {} instead of {}", dumped, name));
+            }
+        }
+
+        let p = &self.params;
+        let mut bytes: Vec<u8> = Vec::with_capacity(61 + 4 * self.points.len() * self.dim);
+        bytes.extend_from_slice(SIDECAR_MAGIC);
+        bytes.extend_from_slice(&SIDECAR_VERSION.to_le_bytes());
+        for x in [p.max_nb_connection, p.ef_construction, p.ef_search, p.max_layer] {
+            bytes.extend_from_slice(&(x as u64).to_le_bytes());
+        }
+        bytes.push(p.use_parallel_insert as u8);
+        bytes.extend_from_slice(&(self.dim as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.points.len() as u64).to_le_bytes());
+        for q in &self.points {
+            for &x in q {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        fs::write(sidecar_path(&dir, &name), bytes).map_err(|e| format!("cannot write index sidecar: {}", e))
+    }
+}
+
+/// An index written by `HnswBackend::save`, opened for loading.
+///
+/// hnsw_rs ties a loaded graph to its reload descriptor, kept here, so backends loaded from
+/// the file borrow it and the file must outlive them.
+pub struct HnswIndexFile {
+    io: HnswIo,
+    points: Vec<Vec<f32>>,
+    dim: usize,
+    params: HnswParams,
+}
+
+impl HnswIndexFile {
+    /// Reads and checks the sidecar of the index at `path`. The graph is read by `load`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let (dir, name) = split_path(path.as_ref())?;
+        let bytes = fs::read(sidecar_path(&dir, &name)).map_err(|e| format!("cannot read index sidecar: {}", e))?;
+
+        if bytes.len() < 12 || &bytes[..8] != SIDECAR_MAGIC {
+            return Err("not a tomato hnsw index sidecar".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != SIDECAR_VERSION {
+            return Err(format!("unsupported index sidecar version {}", version));
+        }
+        let mut at = 12;
+        let max_nb_connection = read_u64(&bytes, &mut at)? as usize;
+        let ef_construction = read_u64(&bytes, &mut at)? as usize;
+        let ef_search = read_u64(&bytes, &mut at)? as usize;
+        let max_layer = read_u64(&bytes, &mut at)? as usize;
+        let use_parallel_insert = *bytes.get(at).ok_or("truncated index sidecar")? != 0;
+        at += 1;
+        let dim = read_u64(&bytes, &mut at)? as usize;
+        let n = read_u64(&bytes, &mut at)? as usize;
+
+        let expected = n.checked_mul(dim).and_then(|x| x.checked_mul(4)).and_then(|x| x.checked_add(at));
+        if expected != Some(bytes.len()) {
+            return Err(format!("index sidecar size does not match {} points of dimension {}", n, dim));
+        }
+
+        let mut points: Vec<Vec<f32>> = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut q = Vec::with_capacity(dim);
+            for _ in 0..dim {
+                q.push(f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()));
+                at += 4;
+            }
+            points.push(q);
+        }
+
+        Ok(Self {
+            io: HnswIo::new(&dir, &name),
+            points,
+            dim,
+            params: HnswParams {
+                max_nb_connection,
+                ef_construction,
+                ef_search,
+                max_layer,
+                use_parallel_insert,
+            },
+        })
+    }
+
+    #[inline]
+    pub fn params(&self) -> &HnswParams {
+        &self.params
+    }
+
+    pub fn load(&self) -> Result<HnswBackend<'_, Euclidean>, String> {
+        self.load_with_metric(Euclidean)
+    }
+
+    /// Loads the graph without rebuilding it. The metric must be of the same type as the one
+    /// the index was built with; its parameters, for example a Mahalanobis covariance, are not
+    /// stored and must match too.
+    pub fn load_with_metric<M: Metric>(&self, metric: M) -> Result<HnswBackend<'_, M>, String> {
+        let n = self.points.len();
+        let dim = self.dim;
+        let params = self.params.clone();
+        if n == 0 {
+            return HnswBackend::with_metric(vec![], params, metric);
+        }
+        metric.validate_dim(dim)?;
+
+        let hnsw: Hnsw<'_, f32, MetricDist<M>> = self
+            .io
+            .load_hnsw_with_dist(MetricDist(metric))
+            .map_err(|e| format!("hnsw load failed: {}", e))?;
+
+        if hnsw.get_nb_point() != n {
+            return Err(format!(
+                "index holds {} points, sidecar holds {}",
+                hnsw.get_nb_point(),
+                n
+            ));
+        }
+        if hnsw.get_point_indexation().get_data_dimension() != dim {
+            return Err(format!(
+                "index has dimension {}, sidecar has dimension {}",
+                hnsw.get_point_indexation().get_data_dimension(),
+                dim
+            ));
+        }
+
+        Ok(HnswBackend {
+            points: self.points.clone(),
+            dim,
+            hnsw,
+            params,
+        })
+    }
+}
+
+impl<M: Metric> AnnBackend for HnswBackend<'_, M> {
     fn dim(&self) -> usize {
         self.dim
     }
@@ -120,7 +328,7 @@ impl AnnBackend for HnswBackend {
 
         let query = &self.points[query_index];
 
-        let ef = self.ef_search.max(kk + 1);
+        let ef = self.params.ef_search.max(kk + 1);
         let mut ans = self.hnsw.search(query, kk + 1, ef);
 
         ans.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
@@ -139,4 +347,29 @@ impl AnnBackend for HnswBackend {
 
         out
     }
+
+    fn knn_point_dist2(&self, point: &[f64], k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        validate_query(point, self.dim)?;
+        let kk = k.min(self.len());
+        if kk == 0 {
+            return Ok(vec![]);
+        }
+
+        let query: Vec<f32> = point.iter().map(|&x| x as f32).collect();
+
+        let ef = self.params.ef_search.max(kk);
+        let mut ans = self.hnsw.search(&query, kk, ef);
+
+        ans.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+
+        let mut out: Vec<(usize, f64)> = Vec::with_capacity(kk);
+        for nb in ans {
+            out.push((nb.d_id, (nb.distance as f64) * (nb.distance as f64)));
+            if out.len() == kk {
+                break;
+            }
+        }
+
+        Ok(out)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,278 @@
+#![forbid(unsafe_code)]
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::backend::metric::{AxisMetric, Euclidean};
+use crate::backend::{validate_query, AnnBackend};
+use crate::tomato::TomatoError;
+
+const LEAF_SIZE: usize = 16;
+
+#[derive(Debug, Clone)]
+struct KdNode {
+    start: usize,
+    end: usize,
+    lo: Vec<f64>,
+    hi: Vec<f64>,
+    children: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    d2: f64,
+    idx: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.d2
+            .partial_cmp(&other.d2)
+            .unwrap()
+            .then_with(|| self.idx.cmp(&other.idx))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Exact kNN and range queries in f64 through a kd tree with bounding boxes.
+///
+/// Distances are computed exactly as in `BruteBackend` with the same metric and ties are broken
+/// by index, so both backends return identical neighbor lists.
+#[derive(Debug, Clone)]
+pub struct KdTreeBackend<M: AxisMetric = Euclidean> {
+    points: Vec<Vec<f64>>,
+    dim: usize,
+    idx: Vec<usize>,
+    nodes: Vec<KdNode>,
+    metric: M,
+}
+
+impl KdTreeBackend<Euclidean> {
+    pub fn new(points: Vec<Vec<f64>>) -> Result<Self, String> {
+        Self::with_metric(points, Euclidean)
+    }
+}
+
+impl<M: AxisMetric> KdTreeBackend<M> {
+    pub fn with_metric(points: Vec<Vec<f64>>, metric: M) -> Result<Self, String> {
+        if points.is_empty() {
+            return Ok(Self {
+                points,
+                dim: 0,
+                idx: vec![],
+                nodes: vec![],
+                metric,
+            });
+        }
+        let dim = points[0].len();
+        for (i, p) in points.iter().enumerate() {
+            if p.len() != dim {
+                return Err(format!("dimension mismatch at row {}", i));
+            }
+            for &x in p {
+                if !x.is_finite() {
+                    return Err("non finite point value".to_string());
+                }
+            }
+        }
+
+        metric.validate_dim(dim)?;
+
+        let mut tree = Self {
+            idx: (0..points.len()).collect(),
+            points,
+            dim,
+            nodes: Vec::new(),
+            metric,
+        };
+        let n = tree.points.len();
+        tree.build(0, n);
+        Ok(tree)
+    }
+
+    #[inline]
+    pub fn metric(&self) -> &M {
+        &self.metric
+    }
+
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let mut lo = vec![f64::INFINITY; self.dim];
+        let mut hi = vec![f64::NEG_INFINITY; self.dim];
+        for &i in &self.idx[start..end] {
+            let p = &self.points[i];
+            for j in 0..self.dim {
+                lo[j] = lo[j].min(p[j]);
+                hi[j] = hi[j].max(p[j]);
+            }
+        }
+
+        let mut split_dim = 0;
+        let mut spread = 0.0;
+        for j in 0..self.dim {
+            if hi[j] - lo[j] > spread {
+                spread = hi[j] - lo[j];
+                split_dim = j;
+            }
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(KdNode {
+            start,
+            end,
+            lo,
+            hi,
+            children: None,
+        });
+
+        if end - start <= LEAF_SIZE || spread.is_nan() || spread <= 0.0 {
+            return id;
+        }
+
+        let mid = start + (end - start) / 2;
+        let points = &self.points;
+        self.idx[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+            points[a][split_dim]
+                .partial_cmp(&points[b][split_dim])
+                .unwrap()
+        });
+
+        let left = self.build(start, mid);
+        let right = self.build(mid, end);
+        self.nodes[id].children = Some((left, right));
+        id
+    }
+
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        self.metric.dist2(a, b)
+    }
+
+    #[inline]
+    fn box_dist2(&self, node: &KdNode, q: &[f64]) -> f64 {
+        self.metric.box_dist2(q, &node.lo, &node.hi)
+    }
+
+    fn knn_search<F: Fn(usize) -> Option<f64>>(
+        &self,
+        node_id: usize,
+        q: &[f64],
+        k: usize,
+        dist: &F,
+        heap: &mut BinaryHeap<Candidate>,
+    ) {
+        let node = &self.nodes[node_id];
+        match node.children {
+            None => {
+                for &i in &self.idx[node.start..node.end] {
+                    let Some(d2) = dist(i) else {
+                        continue;
+                    };
+                    let c = Candidate { d2, idx: i };
+                    if heap.len() < k {
+                        heap.push(c);
+                    } else if c < *heap.peek().unwrap() {
+                        heap.pop();
+                        heap.push(c);
+                    }
+                }
+            }
+            Some((left, right)) => {
+                let dl = self.box_dist2(&self.nodes[left], q);
+                let dr = self.box_dist2(&self.nodes[right], q);
+                let near_first = if dl <= dr {
+                    [(left, dl), (right, dr)]
+                } else {
+                    [(right, dr), (left, dl)]
+                };
+                for (child, bd) in near_first {
+                    if heap.len() == k && bd > heap.peek().unwrap().d2 {
+                        continue;
+                    }
+                    self.knn_search(child, q, k, dist, heap);
+                }
+            }
+        }
+    }
+
+    fn knn<F: Fn(usize) -> Option<f64>>(&self, q: &[f64], k: usize, dist: F) -> Vec<(usize, f64)> {
+        if k == 0 || self.nodes.is_empty() {
+            return vec![];
+        }
+        let mut heap: BinaryHeap<Candidate> = BinaryHeap::with_capacity(k + 1);
+        self.knn_search(0, q, k, &dist, &mut heap);
+        heap.into_sorted_vec().into_iter().map(|c| (c.idx, c.d2)).collect()
+    }
+
+    fn range<F: Fn(usize) -> Option<f64>>(&self, q: &[f64], radius2: f64, dist: F) -> Vec<(usize, f64)> {
+        let mut out: Vec<(usize, f64)> = Vec::new();
+        if self.nodes.is_empty() {
+            return out;
+        }
+        let mut stack: Vec<usize> = vec![0];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            if self.box_dist2(node, q) > radius2 {
+                continue;
+            }
+            match node.children {
+                None => {
+                    for &i in &self.idx[node.start..node.end] {
+                        if let Some(d2) = dist(i) {
+                            if d2 <= radius2 {
+                                out.push((i, d2));
+                            }
+                        }
+                    }
+                }
+                Some((left, right)) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        out
+    }
+}
+
+impl<M: AxisMetric> AnnBackend for KdTreeBackend<M> {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn knn_indices_dist2(&self, query_index: usize, k: usize) -> Vec<(usize, f64)> {
+        let q = &self.points[query_index];
+        let kk = k.min(self.len().saturating_sub(1));
+        self.knn(q, kk, |j| {
+            (j != query_index).then(|| self.dist2(q, &self.points[j]))
+        })
+    }
+
+    fn knn_point_dist2(&self, point: &[f64], k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        validate_query(point, self.dim)?;
+        let kk = k.min(self.len());
+        Ok(self.knn(point, kk, |j| Some(self.dist2(&self.points[j], point))))
+    }
+
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        let q = &self.points[query_index];
+        self.range(q, radius2, |j| {
+            (j != query_index).then(|| self.dist2(q, &self.points[j]))
+        })
+    }
+
+    fn range_point_dist2(&self, point: &[f64], radius2: f64) -> Result<Vec<(usize, f64)>, TomatoError> {
+        validate_query(point, self.dim)?;
+        Ok(self.range(point, radius2, |j| Some(self.dist2(&self.points[j], point))))
+    }
+}
@@ -0,0 +1,325 @@
+#![forbid(unsafe_code)]
+
+/// A distance on points, reported squared.
+///
+/// Every backend works with `dist2`, the square of the metric distance, so `radius2` in
+/// `GraphSpec` and `bandwidth2` in `DensitySpec` keep their meaning of r squared and sigma
+/// squared under any metric.
+pub trait Metric: Clone + Send + Sync + 'static {
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64;
+
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32;
+
+    fn validate_dim(&self, _dim: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A metric that is bounded below through per axis gaps, as required by the kd tree.
+pub trait AxisMetric: Metric {
+    /// Lower bound of `dist2(q, p)` over all points p in the box `[lo, hi]`.
+    fn box_dist2(&self, q: &[f64], lo: &[f64], hi: &[f64]) -> f64;
+}
+
+#[inline]
+fn gap(q: f64, lo: f64, hi: f64) -> f64 {
+    if q < lo {
+        lo - q
+    } else if q > hi {
+        q - hi
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Euclidean;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Manhattan;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Chebyshev;
+
+/// One minus the cosine similarity. A zero vector is at distance one from everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cosine;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Minkowski {
+    p: f64,
+}
+
+impl Minkowski {
+    pub fn new(p: f64) -> Result<Self, String> {
+        if !p.is_finite() || p < 1.0 {
+            return Err("minkowski p must be finite and >= 1".to_string());
+        }
+        Ok(Self { p })
+    }
+
+    #[inline]
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+}
+
+/// Mahalanobis distance for a user supplied covariance matrix.
+///
+/// Stores the inverse Cholesky factor W of the covariance, so that dist2 is the squared norm
+/// of W (a - b).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mahalanobis {
+    dim: usize,
+    whiten: Vec<f64>,
+    whiten_f32: Vec<f32>,
+}
+
+impl Mahalanobis {
+    pub fn new(covariance: Vec<Vec<f64>>) -> Result<Self, String> {
+        let d = covariance.len();
+        for (i, row) in covariance.iter().enumerate() {
+            if row.len() != d {
+                return Err(format!("covariance row {} has length {}, expected {}", i, row.len(), d));
+            }
+            for (j, &x) in row.iter().enumerate() {
+                if !x.is_finite() {
+                    return Err("non finite covariance value".to_string());
+                }
+                if (x - covariance[j][i]).abs() > 1e-12 * (1.0 + x.abs()) {
+                    return Err("covariance must be symmetric".to_string());
+                }
+            }
+        }
+
+        let mut l = vec![0.0; d * d];
+        for i in 0..d {
+            for j in 0..=i {
+                let mut s = covariance[i][j];
+                for t in 0..j {
+                    s -= l[i * d + t] * l[j * d + t];
+                }
+                if i == j {
+                    if s.is_nan() || s <= 0.0 {
+                        return Err("covariance must be positive definite".to_string());
+                    }
+                    l[i * d + i] = s.sqrt();
+                } else {
+                    l[i * d + j] = s / l[j * d + j];
+                }
+            }
+        }
+
+        let mut whiten = vec![0.0; d * d];
+        for c in 0..d {
+            for i in c..d {
+                let mut s = if i == c { 1.0 } else { 0.0 };
+                for t in c..i {
+                    s -= l[i * d + t] * whiten[t * d + c];
+                }
+                whiten[i * d + c] = s / l[i * d + i];
+            }
+        }
+
+        let whiten_f32 = whiten.iter().map(|&x| x as f32).collect();
+        Ok(Self {
+            dim: d,
+            whiten,
+            whiten_f32,
+        })
+    }
+}
+
+impl Metric for Euclidean {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..a.len() {
+            let d = a[j] - b[j];
+            s += d * d;
+        }
+        s
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b.iter()).map(|t| (*t.0 - *t.1) * (*t.0 - *t.1)).sum()
+    }
+}
+
+impl AxisMetric for Euclidean {
+    #[inline]
+    fn box_dist2(&self, q: &[f64], lo: &[f64], hi: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..q.len() {
+            let d = gap(q[j], lo[j], hi[j]);
+            s += d * d;
+        }
+        s
+    }
+}
+
+impl Metric for Manhattan {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..a.len() {
+            s += (a[j] - b[j]).abs();
+        }
+        s * s
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        let s: f32 = a.iter().zip(b.iter()).map(|t| (*t.0 - *t.1).abs()).sum();
+        s * s
+    }
+}
+
+impl AxisMetric for Manhattan {
+    #[inline]
+    fn box_dist2(&self, q: &[f64], lo: &[f64], hi: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..q.len() {
+            s += gap(q[j], lo[j], hi[j]);
+        }
+        s * s
+    }
+}
+
+impl Metric for Chebyshev {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let mut m: f64 = 0.0;
+        for j in 0..a.len() {
+            m = m.max((a[j] - b[j]).abs());
+        }
+        m * m
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        let m = a.iter().zip(b.iter()).fold(0.0f32, |m, t| m.max((*t.0 - *t.1).abs()));
+        m * m
+    }
+}
+
+impl AxisMetric for Chebyshev {
+    #[inline]
+    fn box_dist2(&self, q: &[f64], lo: &[f64], hi: &[f64]) -> f64 {
+        let mut m: f64 = 0.0;
+        for j in 0..q.len() {
+            m = m.max(gap(q[j], lo[j], hi[j]));
+        }
+        m * m
+    }
+}
+
+impl Metric for Minkowski {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..a.len() {
+            s += (a[j] - b[j]).abs().powf(self.p);
+        }
+        s.powf(2.0 / self.p)
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        let p = self.p as f32;
+        let s: f32 = a.iter().zip(b.iter()).map(|t| (*t.0 - *t.1).abs().powf(p)).sum();
+        s.powf(2.0 / p)
+    }
+}
+
+impl AxisMetric for Minkowski {
+    #[inline]
+    fn box_dist2(&self, q: &[f64], lo: &[f64], hi: &[f64]) -> f64 {
+        let mut s = 0.0;
+        for j in 0..q.len() {
+            s += gap(q[j], lo[j], hi[j]).powf(self.p);
+        }
+        s.powf(2.0 / self.p)
+    }
+}
+
+impl Metric for Cosine {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let mut dot = 0.0;
+        let mut na = 0.0;
+        let mut nb = 0.0;
+        for j in 0..a.len() {
+            dot += a[j] * b[j];
+            na += a[j] * a[j];
+            nb += b[j] * b[j];
+        }
+        let cos = if na > 0.0 && nb > 0.0 {
+            (dot / (na.sqrt() * nb.sqrt())).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let d = 1.0 - cos;
+        d * d
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        let mut dot = 0.0f32;
+        let mut na = 0.0f32;
+        let mut nb = 0.0f32;
+        for j in 0..a.len() {
+            dot += a[j] * b[j];
+            na += a[j] * a[j];
+            nb += b[j] * b[j];
+        }
+        let cos = if na > 0.0 && nb > 0.0 {
+            (dot / (na.sqrt() * nb.sqrt())).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let d = 1.0 - cos;
+        d * d
+    }
+}
+
+impl Metric for Mahalanobis {
+    #[inline]
+    fn dist2(&self, a: &[f64], b: &[f64]) -> f64 {
+        let d = self.dim;
+        let mut s = 0.0;
+        for i in 0..d {
+            let mut y = 0.0;
+            for j in 0..=i {
+                y += self.whiten[i * d + j] * (a[j] - b[j]);
+            }
+            s += y * y;
+        }
+        s
+    }
+
+    #[inline]
+    fn dist2_f32(&self, a: &[f32], b: &[f32]) -> f32 {
+        let d = self.dim;
+        let mut s = 0.0f32;
+        for i in 0..d {
+            let mut y = 0.0f32;
+            for j in 0..=i {
+                y += self.whiten_f32[i * d + j] * (a[j] - b[j]);
+            }
+            s += y * y;
+        }
+        s
+    }
+
+    fn validate_dim(&self, dim: usize) -> Result<(), String> {
+        if dim != self.dim {
+            return Err(format!(
+                "mahalanobis covariance has dimension {}, points have dimension {}",
+                self.dim, dim
+            ));
+        }
+        Ok(())
+    }
+}
@@ -2,16 +2,75 @@
 
 pub mod brute;
 pub mod hnsw;
+pub mod kdtree;
+pub mod metric;
+pub mod precomputed;
 
 pub use brute::BruteBackend;
-pub use hnsw::{HnswBackend, HnswParams};
+pub use hnsw::{HnswBackend, HnswIndexFile, HnswParams, MetricDist};
+pub use kdtree::KdTreeBackend;
+pub use precomputed::PrecomputedBackend;
+pub use metric::{AxisMetric, Chebyshev, Cosine, Euclidean, Mahalanobis, Manhattan, Metric, Minkowski};
+
+use crate::tomato::TomatoError;
+
+pub(crate) fn validate_query(point: &[f64], dim: usize) -> Result<(), TomatoError> {
+    if point.len() != dim {
+        return Err(TomatoError::QueryDimensionMismatch {
+            expected: dim,
+            got: point.len(),
+        });
+    }
+    for (i, &x) in point.iter().enumerate() {
+        if !x.is_finite() {
+            return Err(TomatoError::NonFiniteQuery(i));
+        }
+    }
+    Ok(())
+}
 
 pub trait AnnBackend {
     fn dim(&self) -> usize;
     fn len(&self) -> usize;
 
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn knn_indices_dist2(&self, query_index: usize, k: usize) -> Vec<(usize, f64)>;
 
+    /// The k nearest stored points to a new point. Backends without coordinates for new
+    /// points keep this default, which returns `UnsupportedQuery`.
+    fn knn_point_dist2(&self, _point: &[f64], _k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        Err(TomatoError::UnsupportedQuery("this backend does not answer queries at new points".to_string()))
+    }
+
+    fn knn_points_dist2(
+        &self,
+        points: &[Vec<f64>],
+        k: usize,
+    ) -> Result<Vec<Vec<(usize, f64)>>, TomatoError> {
+        let mut all = Vec::with_capacity(points.len());
+        for p in points {
+            all.push(self.knn_point_dist2(p, k)?);
+        }
+        Ok(all)
+    }
+
+    /// All stored points within squared distance `radius2` of a stored point, itself excluded,
+    /// in no particular order.
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        let mut nbrs = self.knn_indices_dist2(query_index, self.len().saturating_sub(1));
+        nbrs.retain(|&(_j, d2)| d2 <= radius2);
+        nbrs
+    }
+
+    fn range_point_dist2(&self, point: &[f64], radius2: f64) -> Result<Vec<(usize, f64)>, TomatoError> {
+        let mut nbrs = self.knn_point_dist2(point, self.len())?;
+        nbrs.retain(|&(_j, d2)| d2 <= radius2);
+        Ok(nbrs)
+    }
+
     fn knn_all_indices_dist2(&self, k: usize) -> Vec<Vec<(usize, f64)>> {
         let n = self.len();
         let mut all = Vec::with_capacity(n);
@@ -0,0 +1,131 @@
+#![forbid(unsafe_code)]
+
+use crate::backend::AnnBackend;
+use crate::tomato::TomatoError;
+
+/// Exact neighbor queries over a user supplied distance matrix, without coordinates.
+///
+/// Distances are stored squared in condensed form, row i holding the pairs (i, j) with j > i as
+/// in scipy's pdist. The diagonal of a dense matrix is ignored. `dim()` returns the intrinsic
+/// dimension given through `with_intrinsic_dim`, zero if none was given; `KnnLog` densities
+/// need a nonzero dimension.
+#[derive(Debug, Clone)]
+pub struct PrecomputedBackend {
+    n: usize,
+    dist2: Vec<f64>,
+    dim: usize,
+}
+
+fn validate_distance(d: f64, i: usize, j: usize) -> Result<(), String> {
+    if !d.is_finite() || d < 0.0 {
+        return Err(format!("invalid distance {} between {} and {}", d, i, j));
+    }
+    Ok(())
+}
+
+impl PrecomputedBackend {
+    pub fn from_dense(matrix: Vec<Vec<f64>>) -> Result<Self, String> {
+        let n = matrix.len();
+        for (i, row) in matrix.iter().enumerate() {
+            if row.len() != n {
+                return Err(format!("row {} has length {}, expected {}", i, row.len(), n));
+            }
+        }
+
+        let mut dist2 = Vec::with_capacity(n * n.saturating_sub(1) / 2);
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, &d) in row.iter().enumerate().skip(i + 1) {
+                validate_distance(d, i, j)?;
+                if d != matrix[j][i] {
+                    return Err(format!("distance matrix is not symmetric at ({}, {})", i, j));
+                }
+                dist2.push(d * d);
+            }
+        }
+        Ok(Self { n, dist2, dim: 0 })
+    }
+
+    pub fn from_condensed(n: usize, condensed: Vec<f64>) -> Result<Self, String> {
+        let expected = n * n.saturating_sub(1) / 2;
+        if condensed.len() != expected {
+            return Err(format!(
+                "condensed matrix has length {}, expected {} for {} points",
+                condensed.len(),
+                expected,
+                n
+            ));
+        }
+
+        let mut dist2 = condensed;
+        let mut t = 0;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                validate_distance(dist2[t], i, j)?;
+                dist2[t] *= dist2[t];
+                t += 1;
+            }
+        }
+        Ok(Self { n, dist2, dim: 0 })
+    }
+
+    pub fn with_intrinsic_dim(mut self, dim: usize) -> Self {
+        self.dim = dim;
+        self
+    }
+
+    #[inline]
+    pub fn dist2(&self, a: usize, b: usize) -> f64 {
+        if a == b {
+            return 0.0;
+        }
+        let (i, j) = if a < b { (a, b) } else { (b, a) };
+        // rows 0..i hold n - 1, n - 2, ..., n - i pairs
+        let row = i * (2 * self.n - i - 1) / 2;
+        self.dist2[row + j - i - 1]
+    }
+}
+
+impl AnnBackend for PrecomputedBackend {
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn len(&self) -> usize {
+        self.n
+    }
+
+    fn knn_indices_dist2(&self, query_index: usize, k: usize) -> Vec<(usize, f64)> {
+        let n = self.n;
+        let kk = k.min(n.saturating_sub(1));
+        let mut buf: Vec<(usize, f64)> = Vec::with_capacity(n.saturating_sub(1));
+        for j in 0..n {
+            if j == query_index {
+                continue;
+            }
+            buf.push((j, self.dist2(query_index, j)));
+        }
+        buf.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        buf.truncate(kk);
+        buf
+    }
+
+    fn knn_point_dist2(&self, _point: &[f64], _k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        Err(TomatoError::UnsupportedQuery(
+            "precomputed distances have no coordinates for new points".to_string(),
+        ))
+    }
+
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        let mut out: Vec<(usize, f64)> = Vec::new();
+        for j in 0..self.n {
+            if j == query_index {
+                continue;
+            }
+            let d2 = self.dist2(query_index, j);
+            if d2 <= radius2 {
+                out.push((j, d2));
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,481 @@
+#![forbid(unsafe_code)]
+
+use std::str::FromStr;
+
+use tomato::backend::HnswParams;
+use tomato::pipeline::{AdaptiveKde, DensitySpec, GraphSpec, Kernel, Smoothing, SmoothingMethod};
+use tomato::NoisePolicy;
+
+pub const USAGE: &str = "\
+usage: tomato cluster <input> [options]
+       tomato diagram <input> [options]
+
+cluster reads points from a CSV or TSV file, or standard input when <input> is -, and writes
+one line per row with its cluster label, density and mode.
+
+diagram runs the graph and density stages with tau = infinity and writes the persistence
+diagram, one line per mode with its birth, death and prominence, together with a suggested
+tau. With --svg it also draws the diagram.
+
+input
+  --delimiter <c>         field delimiter: a single character or tab, default from the
+                          extension (.tsv and .tab use tab), otherwise comma
+  --no-header             the first line is data, columns are named by index
+  --columns <list>        comma separated column names or 0 based indices, default all
+  --zscore                standardize every column to mean 0 and variance 1
+  --weights <column>      name or 0 based index of a column of non negative row weights, such
+                          as multiplicities, that scale each row's density contribution
+
+backend
+  --backend <b>           kdtree (default), brute or hnsw
+  --ef-search <n>         hnsw search width, default 64
+  --ef-construction <n>   hnsw build width, default 200
+  --max-connections <n>   hnsw graph degree, default 24
+
+graph
+  --graph <g>             knn (default), rips or rips-knn
+  --graph-k <n>           neighbors for knn and rips-knn, default 15
+  --radius2 <r2>          squared radius for rips and rips-knn
+  --no-symmetrize         keep the directed knn lists
+
+density
+  --density <d>           knn-log (default), dtm, kde-knn, kde-full, or the adaptive
+                          kde-balloon and kde-sample-point
+  --density-k <n>         neighbors for the knn based densities, default 15
+  --bandwidth2 <s2>       squared bandwidth for the kde densities
+  --kernel <k>            kernel of kde-knn and kde-full: gaussian (default), epanechnikov,
+                          tophat, triweight, laplacian or cauchy; kde-full sums the compact
+                          kernels over their support by range search
+  --dtm-p <p>             power of the neighbor distances averaged by dtm, default 2
+  --eps <e>               added to the squared knn radius in knn-log and dtm, default 1e-12
+  --smooth <m>            smooth the density over the graph before clustering: mean, median
+                          or weighted-mean
+  --smooth-iterations <n> smoothing rounds, default 1
+  --self-weight <w>       weighted-mean only: weight of a point against 1 per neighbor
+
+clustering
+  --tau <t>               prominence threshold, or auto for the largest diagram gap (default)
+  --clusters <n>          choose tau so that exactly n clusters survive
+  --fraction <x>          tau as this fraction of the density span of the diagram
+  --min-density <x>       cluster only: label points below this density as noise
+  --min-cluster-size <n>  cluster only: label clusters with fewer points as noise
+  --min-prominence <x>    cluster only: label clusters with a less prominent mode as noise
+
+output
+  --output <path>         default standard output
+  --format <f>            cluster only: csv or json, default from the output extension,
+                          otherwise csv
+  --svg <path>            diagram only: also draw the diagram and the tau line as SVG
+  --quiet                 no summary on standard error
+";
+
+/// A command line error, reported together with the usage text.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Brute,
+    KdTree,
+    Hnsw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TauChoice {
+    Value(f64),
+    LargestGap,
+    Clusters(usize),
+    Fraction(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputOptions {
+    pub path: String,
+    pub delimiter: Option<u8>,
+    pub header: bool,
+    pub columns: Option<Vec<String>>,
+    pub zscore: bool,
+    pub weights: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub backend: BackendKind,
+    pub hnsw: HnswParams,
+    pub graph: GraphSpec,
+    pub density: DensitySpec,
+    pub smoothing: Option<Smoothing>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterOptions {
+    pub input: InputOptions,
+    pub pipeline: PipelineOptions,
+    pub tau: TauChoice,
+    pub noise: NoisePolicy,
+    pub output: Option<String>,
+    pub format: Format,
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagramOptions {
+    pub input: InputOptions,
+    pub pipeline: PipelineOptions,
+    pub tau: TauChoice,
+    pub output: Option<String>,
+    pub svg: Option<String>,
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Help,
+    Cluster(Box<ClusterOptions>),
+    Diagram(Box<DiagramOptions>),
+}
+
+struct Flags {
+    args: std::vec::IntoIter<String>,
+}
+
+impl Flags {
+    fn value(&mut self, flag: &str) -> Result<String, UsageError> {
+        self.args
+            .next()
+            .ok_or_else(|| UsageError(format!("{} needs a value", flag)))
+    }
+
+    fn parse<T: FromStr>(&mut self, flag: &str) -> Result<T, UsageError> {
+        let v = self.value(flag)?;
+        v.parse()
+            .map_err(|_| UsageError(format!("invalid value {:?} for {}", v, flag)))
+    }
+}
+
+fn parse_delimiter(v: &str) -> Result<u8, UsageError> {
+    match v {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if v.len() == 1 && v.is_ascii() => Ok(v.as_bytes()[0]),
+        _ => Err(UsageError(format!("invalid delimiter {:?}", v))),
+    }
+}
+
+fn require<T>(v: Option<T>, flag: &str, what: &str) -> Result<T, UsageError> {
+    v.ok_or_else(|| UsageError(format!("{} needs {}", what, flag)))
+}
+
+pub fn parse(args: Vec<String>) -> Result<Command, UsageError> {
+    let mut flags = Flags { args: args.into_iter() };
+    match flags.args.next().as_deref() {
+        None | Some("help") | Some("-h") | Some("--help") => Ok(Command::Help),
+        Some("cluster") => Ok(Command::Cluster(Box::new(parse_cluster(flags)?))),
+        Some("diagram") => Ok(Command::Diagram(Box::new(parse_diagram(flags)?))),
+        Some(other) => Err(UsageError(format!("unknown command {:?}", other))),
+    }
+}
+
+// Input, backend, graph, density and tau flags, shared by every command.
+struct Common {
+    path: Option<String>,
+    delimiter: Option<u8>,
+    header: bool,
+    columns: Option<Vec<String>>,
+    zscore: bool,
+    weights: Option<String>,
+
+    backend: BackendKind,
+    hnsw: HnswParams,
+
+    graph: String,
+    graph_k: usize,
+    radius2: Option<f64>,
+    symmetrize: bool,
+
+    density: String,
+    density_k: usize,
+    bandwidth2: Option<f64>,
+    kernel: Kernel,
+    dtm_p: f64,
+    eps: f64,
+
+    smooth: Option<String>,
+    smooth_iterations: usize,
+    self_weight: Option<f64>,
+
+    tau: TauChoice,
+    quiet: bool,
+}
+
+impl Common {
+    fn new() -> Self {
+        Self {
+            path: None,
+            delimiter: None,
+            header: true,
+            columns: None,
+            zscore: false,
+            weights: None,
+            backend: BackendKind::KdTree,
+            hnsw: HnswParams::default(),
+            graph: "knn".to_string(),
+            graph_k: 15,
+            radius2: None,
+            symmetrize: true,
+            density: "knn-log".to_string(),
+            density_k: 15,
+            bandwidth2: None,
+            kernel: Kernel::Gaussian,
+            dtm_p: 2.0,
+            eps: 1e-12,
+            smooth: None,
+            smooth_iterations: 1,
+            self_weight: None,
+            tau: TauChoice::LargestGap,
+            quiet: false,
+        }
+    }
+
+    /// Consumes `arg` and its value if it is a shared flag or the input path.
+    fn flag(&mut self, arg: &str, flags: &mut Flags) -> Result<bool, UsageError> {
+        match arg {
+            "--delimiter" => self.delimiter = Some(parse_delimiter(&flags.value(arg)?)?),
+            "--no-header" => self.header = false,
+            "--columns" => {
+                let v = flags.value(arg)?;
+                self.columns = Some(v.split(',').map(|c| c.trim().to_string()).collect());
+            }
+            "--zscore" => self.zscore = true,
+            "--weights" => self.weights = Some(flags.value(arg)?),
+            "--backend" => {
+                self.backend = match flags.value(arg)?.as_str() {
+                    "brute" => BackendKind::Brute,
+                    "kdtree" => BackendKind::KdTree,
+                    "hnsw" => BackendKind::Hnsw,
+                    v => return Err(UsageError(format!("unknown backend {:?}", v))),
+                }
+            }
+            "--ef-search" => self.hnsw.ef_search = flags.parse(arg)?,
+            "--ef-construction" => self.hnsw.ef_construction = flags.parse(arg)?,
+            "--max-connections" => self.hnsw.max_nb_connection = flags.parse(arg)?,
+            "--graph" => self.graph = flags.value(arg)?,
+            "--graph-k" => self.graph_k = flags.parse(arg)?,
+            "--radius2" => self.radius2 = Some(flags.parse(arg)?),
+            "--no-symmetrize" => self.symmetrize = false,
+            "--density" => self.density = flags.value(arg)?,
+            "--density-k" => self.density_k = flags.parse(arg)?,
+            "--bandwidth2" => self.bandwidth2 = Some(flags.parse(arg)?),
+            "--kernel" => {
+                self.kernel = match flags.value(arg)?.as_str() {
+                    "gaussian" => Kernel::Gaussian,
+                    "epanechnikov" => Kernel::Epanechnikov,
+                    "tophat" => Kernel::Tophat,
+                    "triweight" => Kernel::Triweight,
+                    "laplacian" => Kernel::Laplacian,
+                    "cauchy" => Kernel::Cauchy,
+                    v => return Err(UsageError(format!("unknown kernel {:?}", v))),
+                }
+            }
+            "--dtm-p" => self.dtm_p = flags.parse(arg)?,
+            "--eps" => self.eps = flags.parse(arg)?,
+            "--smooth" => self.smooth = Some(flags.value(arg)?),
+            "--smooth-iterations" => self.smooth_iterations = flags.parse(arg)?,
+            "--self-weight" => self.self_weight = Some(flags.parse(arg)?),
+            "--tau" => {
+                let v = flags.value(arg)?;
+                self.tau = if v == "auto" {
+                    TauChoice::LargestGap
+                } else {
+                    TauChoice::Value(
+                        v.parse()
+                            .map_err(|_| UsageError(format!("invalid value {:?} for --tau", v)))?,
+                    )
+                };
+            }
+            "--clusters" => self.tau = TauChoice::Clusters(flags.parse(arg)?),
+            "--fraction" => self.tau = TauChoice::Fraction(flags.parse(arg)?),
+            "--quiet" | "-q" => self.quiet = true,
+            _ if arg.starts_with('-') && arg != "-" => return Ok(false),
+            _ => {
+                if self.path.is_some() {
+                    return Err(UsageError(format!("unexpected argument {:?}", arg)));
+                }
+                self.path = Some(arg.to_string());
+            }
+        }
+        Ok(true)
+    }
+
+    fn finish(self) -> Result<(InputOptions, PipelineOptions, TauChoice, bool), UsageError> {
+        let path = self.path.ok_or_else(|| UsageError("missing input file".to_string()))?;
+
+        let graph = match self.graph.as_str() {
+            "knn" => GraphSpec::Knn {
+                k: self.graph_k,
+                symmetrize: self.symmetrize,
+            },
+            "rips" => GraphSpec::RipsBrute {
+                radius2: require(self.radius2, "--radius2", "--graph rips")?,
+            },
+            "rips-knn" => GraphSpec::RipsFromKnnApprox {
+                k: self.graph_k,
+                radius2: require(self.radius2, "--radius2", "--graph rips-knn")?,
+                symmetrize: self.symmetrize,
+            },
+            v => return Err(UsageError(format!("unknown graph {:?}", v))),
+        };
+
+        let density = match self.density.as_str() {
+            "knn-log" => DensitySpec::KnnLog {
+                k: self.density_k,
+                eps: self.eps,
+            },
+            "dtm" => DensitySpec::Dtm {
+                k: self.density_k,
+                p: self.dtm_p,
+                eps: self.eps,
+            },
+            "kde-knn" => {
+                let bandwidth2 = require(self.bandwidth2, "--bandwidth2", "--density kde-knn")?;
+                match self.kernel {
+                    Kernel::Gaussian => DensitySpec::KdeGaussianKnn {
+                        k: self.density_k,
+                        bandwidth2,
+                    },
+                    kernel => DensitySpec::KdeKnn {
+                        kernel,
+                        k: self.density_k,
+                        bandwidth2,
+                    },
+                }
+            }
+            "kde-full" => {
+                let bandwidth2 = require(self.bandwidth2, "--bandwidth2", "--density kde-full")?;
+                match self.kernel {
+                    Kernel::Gaussian => DensitySpec::KdeGaussianFullBrute { bandwidth2 },
+                    kernel => DensitySpec::KdeRange { kernel, bandwidth2 },
+                }
+            }
+            "kde-balloon" => DensitySpec::KdeGaussianAdaptive {
+                k: self.density_k,
+                bandwidth2: require(self.bandwidth2, "--bandwidth2", "--density kde-balloon")?,
+                method: AdaptiveKde::Balloon,
+            },
+            "kde-sample-point" => DensitySpec::KdeGaussianAdaptive {
+                k: self.density_k,
+                bandwidth2: require(self.bandwidth2, "--bandwidth2", "--density kde-sample-point")?,
+                method: AdaptiveKde::SamplePoint,
+            },
+            v => return Err(UsageError(format!("unknown density {:?}", v))),
+        };
+
+        let smoothing = match self.smooth.as_deref() {
+            None => None,
+            Some(m) => {
+                let method = match m {
+                    "mean" => SmoothingMethod::Mean,
+                    "median" => SmoothingMethod::Median,
+                    "weighted-mean" => SmoothingMethod::WeightedMean {
+                        self_weight: require(self.self_weight, "--self-weight", "--smooth weighted-mean")?,
+                    },
+                    v => return Err(UsageError(format!("unknown smoothing {:?}", v))),
+                };
+                Some(Smoothing::new(method, self.smooth_iterations))
+            }
+        };
+
+        let input = InputOptions {
+            path,
+            delimiter: self.delimiter,
+            header: self.header,
+            columns: self.columns,
+            zscore: self.zscore,
+            weights: self.weights,
+        };
+        let pipeline = PipelineOptions {
+            backend: self.backend,
+            hnsw: self.hnsw,
+            graph,
+            density,
+            smoothing,
+        };
+        Ok((input, pipeline, self.tau, self.quiet))
+    }
+}
+
+fn parse_cluster(mut flags: Flags) -> Result<ClusterOptions, UsageError> {
+    let mut common = Common::new();
+    let mut noise = NoisePolicy::default();
+    let mut output: Option<String> = None;
+    let mut format: Option<Format> = None;
+
+    while let Some(arg) = flags.args.next() {
+        if common.flag(&arg, &mut flags)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--min-density" => noise.min_density = Some(flags.parse(&arg)?),
+            "--min-cluster-size" => noise.min_cluster_size = Some(flags.parse(&arg)?),
+            "--min-prominence" => noise.min_prominence = Some(flags.parse(&arg)?),
+            "--output" | "-o" => output = Some(flags.value(&arg)?),
+            "--format" => {
+                format = Some(match flags.value(&arg)?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    v => return Err(UsageError(format!("unknown format {:?}", v))),
+                })
+            }
+            _ => return Err(UsageError(format!("unknown option {}", arg))),
+        }
+    }
+
+    let (input, pipeline, tau, quiet) = common.finish()?;
+    let format = match format {
+        Some(f) => f,
+        None if output.as_deref().is_some_and(|o| o.ends_with(".json")) => Format::Json,
+        None => Format::Csv,
+    };
+
+    Ok(ClusterOptions {
+        input,
+        pipeline,
+        tau,
+        noise,
+        output,
+        format,
+        quiet,
+    })
+}
+
+fn parse_diagram(mut flags: Flags) -> Result<DiagramOptions, UsageError> {
+    let mut common = Common::new();
+    let mut output: Option<String> = None;
+    let mut svg: Option<String> = None;
+
+    while let Some(arg) = flags.args.next() {
+        if common.flag(&arg, &mut flags)? {
+            continue;
+        }
+        match arg.as_str() {
+            "--output" | "-o" => output = Some(flags.value(&arg)?),
+            "--svg" => svg = Some(flags.value(&arg)?),
+            _ => return Err(UsageError(format!("unknown option {}", arg))),
+        }
+    }
+
+    let (input, pipeline, tau, quiet) = common.finish()?;
+    Ok(DiagramOptions {
+        input,
+        pipeline,
+        tau,
+        output,
+        svg,
+        quiet,
+    })
+}
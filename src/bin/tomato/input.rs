@@ -0,0 +1,110 @@
+#![forbid(unsafe_code)]
+
+use std::fs::File;
+use std::io::{self, Read};
+
+use tomato::stats::{weighted_zscore_in_place, zscore_in_place};
+
+use crate::args::InputOptions;
+
+fn default_delimiter(path: &str) -> u8 {
+    if path.ends_with(".tsv") || path.ends_with(".tab") {
+        b'\t'
+    } else {
+        b','
+    }
+}
+
+// Maps each selected column, by header name or 0 based index, to its field position.
+fn select_columns(selection: &[String], header: Option<&csv::StringRecord>, width: usize) -> Result<Vec<usize>, String> {
+    let mut cols = Vec::with_capacity(selection.len());
+    for name in selection {
+        let by_name = header.and_then(|h| h.iter().position(|f| f.trim() == name));
+        let col = match by_name {
+            Some(c) => c,
+            None => match name.parse::<usize>() {
+                Ok(c) if c < width => c,
+                Ok(c) => return Err(format!("column {} out of range, the input has {} columns", c, width)),
+                Err(_) => return Err(format!("no column named {:?}", name)),
+            },
+        };
+        cols.push(col);
+    }
+    Ok(cols)
+}
+
+fn parse_field(record: &csv::StringRecord, row: usize, c: usize) -> Result<f64, String> {
+    let field = record.get(c).unwrap_or("");
+    let x: f64 = field
+        .parse()
+        .map_err(|_| format!("row {} column {}: {:?} is not a number", row, c, field))?;
+    if !x.is_finite() {
+        return Err(format!("row {} column {}: non finite value", row, c));
+    }
+    Ok(x)
+}
+
+/// Points and, with `--weights`, one weight per point.
+pub type Rows = (Vec<Vec<f64>>, Option<Vec<f64>>);
+
+/// Reads the selected columns of every row as f64 points, and the weight column if any.
+pub fn read_points(opts: &InputOptions) -> Result<Rows, String> {
+    let source: Box<dyn Read> = if opts.path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&opts.path).map_err(|e| format!("cannot open {}: {}", opts.path, e))?)
+    };
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(opts.delimiter.unwrap_or_else(|| default_delimiter(&opts.path)))
+        .has_headers(opts.header)
+        .trim(csv::Trim::All)
+        .from_reader(source);
+
+    let header = if opts.header {
+        Some(rdr.headers().map_err(|e| format!("cannot read header: {}", e))?.clone())
+    } else {
+        None
+    };
+
+    let mut cols: Option<Vec<usize>> = None;
+    let mut weight_col: Option<usize> = None;
+    let mut points: Vec<Vec<f64>> = Vec::new();
+    let mut weights: Vec<f64> = Vec::new();
+    for (row, record) in rdr.records().enumerate() {
+        let record = record.map_err(|e| format!("row {}: {}", row, e))?;
+        if cols.is_none() {
+            if let Some(w) = &opts.weights {
+                weight_col = Some(select_columns(std::slice::from_ref(w), header.as_ref(), record.len())?[0]);
+            }
+            // the weight column is not a coordinate, unless selected explicitly
+            cols = Some(match &opts.columns {
+                Some(sel) => select_columns(sel, header.as_ref(), record.len())?,
+                None => (0..record.len()).filter(|&c| Some(c) != weight_col).collect(),
+            });
+        }
+        let cols = cols.as_ref().unwrap();
+
+        let mut p = Vec::with_capacity(cols.len());
+        for &c in cols {
+            p.push(parse_field(&record, row, c)?);
+        }
+        points.push(p);
+        if let Some(c) = weight_col {
+            let w = parse_field(&record, row, c)?;
+            if w < 0.0 {
+                return Err(format!("row {} column {}: negative weight", row, c));
+            }
+            weights.push(w);
+        }
+    }
+
+    let weights = weight_col.map(|_| weights);
+    if opts.zscore {
+        match &weights {
+            Some(w) => weighted_zscore_in_place(&mut points, w).map_err(|e| e.to_string())?,
+            None => zscore_in_place(&mut points),
+        }
+    }
+    Ok((points, weights))
+}
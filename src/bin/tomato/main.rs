@@ -0,0 +1,140 @@
+#![forbid(unsafe_code)]
+
+mod args;
+mod input;
+mod output;
+mod svg;
+
+use std::error::Error;
+use std::fmt;
+use std::process::ExitCode;
+
+use tomato::backend::{AnnBackend, BruteBackend, HnswBackend, KdTreeBackend};
+use tomato::hierarchy::MergeTree;
+use tomato::pipeline::{run_pipeline_with_weights, PipelineParams};
+use tomato::selection::{select_tau, TauCriterion, TauSelection};
+use tomato::{Graph, TomatoParams};
+
+use crate::args::{
+    BackendKind, ClusterOptions, Command, DiagramOptions, PipelineOptions, TauChoice, UsageError, USAGE,
+};
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for UsageError {}
+
+// Graph and smoothed density from the library pipeline. Its clustering at tau = infinity is
+// dropped; the merge tree gives the labels once tau is chosen.
+fn stages<B: AnnBackend>(
+    backend: &B,
+    opts: &PipelineOptions,
+    weights: Option<&[f64]>,
+) -> Result<(Graph, Vec<f64>), Box<dyn Error>> {
+    let mut params = PipelineParams::new(opts.graph.clone(), opts.density.clone(), TomatoParams::new(f64::INFINITY));
+    if let Some(smoothing) = opts.smoothing {
+        params = params.with_smoothing(smoothing);
+    }
+    let out = run_pipeline_with_weights(backend, params, weights)?;
+    Ok((out.graph, out.density))
+}
+
+fn run_stages(
+    points: Vec<Vec<f64>>,
+    weights: Option<&[f64]>,
+    opts: &PipelineOptions,
+) -> Result<(Graph, Vec<f64>), Box<dyn Error>> {
+    match opts.backend {
+        BackendKind::Brute => stages(&BruteBackend::new(points)?, opts, weights),
+        BackendKind::KdTree => stages(&KdTreeBackend::new(points)?, opts, weights),
+        BackendKind::Hnsw => stages(&HnswBackend::new(points, opts.hnsw.clone())?, opts, weights),
+    }
+}
+
+// A fixed tau, or one read off the diagram. An empty input has no diagram and keeps every class.
+fn choose_tau(tree: &MergeTree, tau: TauChoice) -> Result<(f64, Option<TauSelection>), Box<dyn Error>> {
+    let criterion = match tau {
+        TauChoice::Value(tau) => return Ok((tau, None)),
+        _ if tree.n() == 0 => return Ok((f64::INFINITY, None)),
+        TauChoice::LargestGap => TauCriterion::LargestGap,
+        TauChoice::Clusters(c) => TauCriterion::NClusters(c),
+        TauChoice::Fraction(x) => TauCriterion::ProminenceFraction(x),
+    };
+    let selection = select_tau(&tree.diagram(), criterion)?;
+    Ok((selection.tau, Some(selection)))
+}
+
+fn cluster(opts: ClusterOptions) -> Result<(), Box<dyn Error>> {
+    let (points, weights) = input::read_points(&opts.input)?;
+    let n = points.len();
+    let (graph, density) = run_stages(points, weights.as_deref(), &opts.pipeline)?;
+    let tree = MergeTree::new(&graph, &density)?;
+
+    let (tau, _) = choose_tau(&tree, opts.tau)?;
+    let result = tree.result(&TomatoParams::new(tau).with_noise(opts.noise.clone()))?;
+
+    let mut out = output::open(opts.output.as_deref())?;
+    output::write_labels(&mut *out, opts.format, &result, &density, tau)?;
+
+    if !opts.quiet {
+        eprintln!(
+            "tomato: {} rows, tau {}, {} clusters, {} noise",
+            n,
+            tau,
+            result.modes.len(),
+            result.n_noise()
+        );
+    }
+    Ok(())
+}
+
+fn diagram(opts: DiagramOptions) -> Result<(), Box<dyn Error>> {
+    let (points, weights) = input::read_points(&opts.input)?;
+    let (graph, density) = run_stages(points, weights.as_deref(), &opts.pipeline)?;
+    let tree = MergeTree::new(&graph, &density)?;
+    let diagram = tree.diagram();
+    let (tau, selection) = choose_tau(&tree, opts.tau)?;
+
+    let mut out = output::open(opts.output.as_deref())?;
+    output::write_diagram(&mut *out, &diagram)?;
+
+    if let Some(path) = &opts.svg {
+        std::fs::write(path, svg::render_diagram(&diagram, tau)).map_err(|e| format!("cannot write {}: {}", path, e))?;
+    }
+
+    if !opts.quiet {
+        match selection {
+            Some(sel) => eprintln!("tomato: {} modes, suggested {}", diagram.len(), sel),
+            None => eprintln!("tomato: {} modes, tau {} keeps {}", diagram.len(), tau, tree.n_clusters_at(tau)?),
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let res = match args::parse(argv) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Ok(Command::Cluster(opts)) => cluster(*opts),
+        Ok(Command::Diagram(opts)) => diagram(*opts),
+        Err(e) => Err(Box::new(e) as Box<dyn Error>),
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is::<UsageError>() => {
+            eprintln!("tomato: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            eprintln!("tomato: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,106 @@
+#![forbid(unsafe_code)]
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use tomato::{PersistencePair, TomatoResult, NOISE};
+
+use crate::args::Format;
+
+pub fn open(path: Option<&str>) -> Result<Box<dyn Write>, String> {
+    match path {
+        None | Some("-") => Ok(Box::new(BufWriter::new(io::stdout()))),
+        Some(p) => {
+            let f = File::create(p).map_err(|e| format!("cannot create {}: {}", p, e))?;
+            Ok(Box::new(BufWriter::new(f)))
+        }
+    }
+}
+
+// JSON has no infinity; an infinite tau is written as null.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Writes one line per row: cluster label, density and mode.
+///
+/// Labels number the clusters 0, 1, ... in mode order, highest mode first. Noise rows get label
+/// -1 and no mode.
+pub fn write_labels(
+    out: &mut dyn Write,
+    format: Format,
+    result: &TomatoResult,
+    density: &[f64],
+    tau: f64,
+) -> io::Result<()> {
+    let mut label_of_mode = vec![usize::MAX; density.len()];
+    for (c, &m) in result.modes.iter().enumerate() {
+        label_of_mode[m] = c;
+    }
+
+    match format {
+        Format::Csv => {
+            writeln!(out, "row,label,density,mode")?;
+            for (v, &m) in result.cluster_of.iter().enumerate() {
+                if m == NOISE {
+                    writeln!(out, "{},-1,{},", v, density[v])?;
+                } else {
+                    writeln!(out, "{},{},{},{}", v, label_of_mode[m], density[v], m)?;
+                }
+            }
+        }
+        Format::Json => {
+            writeln!(out, "{{")?;
+            writeln!(out, "  \"n\": {},", density.len())?;
+            writeln!(out, "  \"tau\": {},", json_number(tau))?;
+            writeln!(out, "  \"n_clusters\": {},", result.modes.len())?;
+            writeln!(out, "  \"n_noise\": {},", result.n_noise())?;
+            let modes: Vec<String> = result.modes.iter().map(|m| m.to_string()).collect();
+            writeln!(out, "  \"modes\": [{}],", modes.join(", "))?;
+            writeln!(out, "  \"rows\": [")?;
+            let n = result.cluster_of.len();
+            for (v, &m) in result.cluster_of.iter().enumerate() {
+                let sep = if v + 1 < n { "," } else { "" };
+                if m == NOISE {
+                    writeln!(
+                        out,
+                        "    {{\"row\": {}, \"label\": -1, \"density\": {}, \"mode\": null}}{}",
+                        v,
+                        json_number(density[v]),
+                        sep
+                    )?;
+                } else {
+                    writeln!(
+                        out,
+                        "    {{\"row\": {}, \"label\": {}, \"density\": {}, \"mode\": {}}}{}",
+                        v,
+                        label_of_mode[m],
+                        json_number(density[v]),
+                        m,
+                        sep
+                    )?;
+                }
+            }
+            writeln!(out, "  ]")?;
+            writeln!(out, "}}")?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes one line per mode: birth, death, prominence and saddle row, in mode order. Essential
+/// classes have no death and no saddle, and an infinite prominence.
+pub fn write_diagram(out: &mut dyn Write, diagram: &[PersistencePair]) -> io::Result<()> {
+    writeln!(out, "mode,birth,death,prominence,saddle")?;
+    for p in diagram {
+        match (p.death, p.saddle) {
+            (Some(d), Some(s)) => writeln!(out, "{},{},{},{},{}", p.mode, p.birth, d, p.prominence(), s)?,
+            _ => writeln!(out, "{},{},,inf,", p.mode, p.birth)?,
+        }
+    }
+    out.flush()
+}
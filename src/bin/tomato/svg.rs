@@ -0,0 +1,137 @@
+#![forbid(unsafe_code)]
+
+use std::fmt::Write;
+
+use tomato::PersistencePair;
+
+const SIZE: f64 = 480.0;
+const MARGIN: f64 = 56.0;
+
+struct Frame {
+    lo: f64,
+    hi: f64,
+}
+
+impl Frame {
+    fn x(&self, v: f64) -> f64 {
+        MARGIN + (v - self.lo) / (self.hi - self.lo) * (SIZE - 2.0 * MARGIN)
+    }
+
+    fn y(&self, v: f64) -> f64 {
+        SIZE - MARGIN - (v - self.lo) / (self.hi - self.lo) * (SIZE - 2.0 * MARGIN)
+    }
+}
+
+/// Draws the diagram with birth on the x axis and death on the y axis.
+///
+/// Every finite pair lies on or below the diagonal, at vertical distance equal to its
+/// prominence. The tau line is the diagonal shifted down by tau: pairs below it are kept.
+/// Essential classes never die and are drawn as triangles on the bottom edge.
+pub fn render_diagram(diagram: &[PersistencePair], tau: f64) -> String {
+    let mut lo = f64::INFINITY;
+    let mut hi = f64::NEG_INFINITY;
+    for p in diagram {
+        lo = lo.min(p.birth).min(p.death.unwrap_or(p.birth));
+        hi = hi.max(p.birth);
+    }
+    if diagram.is_empty() {
+        lo = 0.0;
+        hi = 1.0;
+    }
+    let pad = if hi > lo { 0.05 * (hi - lo) } else { 1.0 };
+    let f = Frame {
+        lo: lo - pad,
+        hi: hi + pad,
+    };
+
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\" font-family=\"sans-serif\" font-size=\"12\">",
+        SIZE
+    );
+    let _ = writeln!(s, "<rect width=\"{0}\" height=\"{0}\" fill=\"white\"/>", SIZE);
+    let _ = writeln!(
+        s,
+        "<rect x=\"{0}\" y=\"{0}\" width=\"{1}\" height=\"{1}\" fill=\"none\" stroke=\"black\"/>",
+        MARGIN,
+        SIZE - 2.0 * MARGIN
+    );
+
+    let _ = writeln!(
+        s,
+        "<line class=\"diagonal\" x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"gray\"/>",
+        f.x(f.lo),
+        f.y(f.lo),
+        f.x(f.hi),
+        f.y(f.hi)
+    );
+
+    if tau.is_finite() && f.lo + tau < f.hi {
+        let _ = writeln!(
+            s,
+            "<line class=\"tau\" x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"firebrick\" stroke-dasharray=\"6 4\"/>",
+            f.x(f.lo + tau),
+            f.y(f.lo),
+            f.x(f.hi),
+            f.y(f.hi - tau)
+        );
+    }
+
+    for p in diagram {
+        let color = if p.prominence() >= tau { "firebrick" } else { "gray" };
+        let cx = f.x(p.birth);
+        match p.death {
+            Some(d) => {
+                let _ = writeln!(
+                    s,
+                    "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"3.5\" fill=\"{}\"><title>mode {} birth {} death {}</title></circle>",
+                    cx,
+                    f.y(d),
+                    color,
+                    p.mode,
+                    p.birth,
+                    d
+                );
+            }
+            None => {
+                let cy = SIZE - MARGIN;
+                let _ = writeln!(
+                    s,
+                    "<polygon points=\"{:.2},{:.2} {:.2},{:.2} {:.2},{:.2}\" fill=\"{}\"><title>mode {} birth {} essential</title></polygon>",
+                    cx,
+                    cy - 7.0,
+                    cx - 5.0,
+                    cy,
+                    cx + 5.0,
+                    cy,
+                    color,
+                    p.mode,
+                    p.birth
+                );
+            }
+        }
+    }
+
+    let base = SIZE - MARGIN;
+    let _ = writeln!(s, "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"middle\">{:.4}</text>", MARGIN, base + 16.0, f.lo);
+    let _ = writeln!(s, "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"middle\">{:.4}</text>", base, base + 16.0, f.hi);
+    let _ = writeln!(s, "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"end\">{:.4}</text>", MARGIN - 4.0, base, f.lo);
+    let _ = writeln!(s, "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"end\">{:.4}</text>", MARGIN - 4.0, MARGIN + 4.0, f.hi);
+    let _ = writeln!(s, "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"middle\">birth</text>", SIZE / 2.0, SIZE - 16.0);
+    let _ = writeln!(
+        s,
+        "<text x=\"16\" y=\"{0:.2}\" text-anchor=\"middle\" transform=\"rotate(-90 16 {0:.2})\">death</text>",
+        SIZE / 2.0
+    );
+    let _ = writeln!(
+        s,
+        "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"middle\">{} modes, tau {}</text>",
+        SIZE / 2.0,
+        MARGIN / 2.0,
+        diagram.len(),
+        tau
+    );
+    s.push_str("</svg>\n");
+    s
+}
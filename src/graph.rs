@@ -2,7 +2,14 @@
 
 use crate::tomato::TomatoError;
 
+/// With the `serde` feature a graph is written as its adjacency lists and checked by `Graph::new`
+/// when read back.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<Vec<usize>>", into = "Vec<Vec<usize>>")
+)]
 pub struct Graph {
     adj: Vec<Vec<usize>>,
 }
@@ -42,11 +49,25 @@ impl Graph {
                 }
             }
         }
-        for u in 0..n {
-            adj[u].sort_unstable();
-            adj[u].dedup();
-            adj[u].retain(|&v| v != u);
+        for (u, nbrs) in adj.iter_mut().enumerate() {
+            nbrs.sort_unstable();
+            nbrs.dedup();
+            nbrs.retain(|&v| v != u);
         }
         adj
     }
+}
+
+impl TryFrom<Vec<Vec<usize>>> for Graph {
+    type Error = TomatoError;
+
+    fn try_from(adj: Vec<Vec<usize>>) -> Result<Self, TomatoError> {
+        Graph::new(adj)
+    }
+}
+
+impl From<Graph> for Vec<Vec<usize>> {
+    fn from(graph: Graph) -> Self {
+        graph.adj
+    }
 }
\ No newline at end of file
@@ -0,0 +1,284 @@
+#![forbid(unsafe_code)]
+
+use crate::graph::Graph;
+use crate::order::{higher, vertices_desc_by_density};
+use crate::persistence::PersistencePair;
+use crate::tomato::{
+    apply_noise, protected_merges, validate_density, Merges, TomatoError, TomatoParams, TomatoResult, NOISE,
+};
+use crate::uf::UfTomato;
+
+/// Merge hierarchy of the vertex superlevel filtration, built once with tau = infinity.
+///
+/// Every vertex is a node. A mode is a vertex with no higher neighbor; it dies at the saddle
+/// where its component meets a component with a higher mode and hangs below that mode. Every
+/// other vertex is a node of prominence zero that hangs below the highest mode among its upper
+/// neighbors. The modes whose prominence is at least tau are the clusters at tau.
+///
+/// Labels at a tau come from the union-find of `tomato_cluster`, replayed over the stored graph
+/// in the stored order: a mode that survives tau stays apart from then on, so a lower
+/// component that later meets it joins it rather than the mode it hangs below here.
+#[derive(Debug, Clone)]
+pub struct MergeTree {
+    graph: Graph,
+    density: Vec<f64>,
+    order: Vec<usize>,
+    parent: Vec<usize>,
+    prominence: Vec<f64>,
+    saddle: Vec<Option<usize>>,
+    is_mode: Vec<bool>,
+    sorted_prominence: Vec<f64>,
+}
+
+fn validate_tau(tau: f64) -> Result<(), TomatoError> {
+    if tau.is_nan() || tau < 0.0 {
+        return Err(TomatoError::InvalidTau);
+    }
+    Ok(())
+}
+
+impl MergeTree {
+    pub fn new(graph: &Graph, density: &[f64]) -> Result<Self, TomatoError> {
+        validate_density(density)?;
+        if density.len() != graph.n() {
+            return Err(TomatoError::DensityLengthMismatch);
+        }
+
+        let order = vertices_desc_by_density(density);
+        let ElderMerges { parent, saddle, is_mode } = elder_merges(graph, density, &order);
+
+        let mut prominence = vec![0.0; graph.n()];
+        for (v, p) in prominence.iter_mut().enumerate() {
+            if is_mode[v] {
+                *p = saddle[v].map_or(f64::INFINITY, |s| density[v] - density[s]);
+            }
+        }
+
+        let mut sorted_prominence = prominence.clone();
+        sorted_prominence.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        Ok(Self {
+            graph: graph.clone(),
+            density: density.to_vec(),
+            order,
+            parent,
+            prominence,
+            saddle,
+            is_mode,
+            sorted_prominence,
+        })
+    }
+
+    #[inline]
+    pub fn n(&self) -> usize {
+        self.order.len()
+    }
+
+    #[inline]
+    pub fn density(&self) -> &[f64] {
+        &self.density
+    }
+
+    /// Vertices in filtration order, higher density first, then smaller vertex id.
+    #[inline]
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// The mode a node hangs below, or the node itself for essential modes.
+    #[inline]
+    pub fn parent(&self, v: usize) -> usize {
+        self.parent[v]
+    }
+
+    /// Zero for non mode vertices, birth minus death for modes, infinite for essential modes.
+    #[inline]
+    pub fn prominence(&self, v: usize) -> f64 {
+        self.prominence[v]
+    }
+
+    #[inline]
+    pub fn saddle(&self, v: usize) -> Option<usize> {
+        self.saddle[v]
+    }
+
+    #[inline]
+    pub fn is_mode(&self, v: usize) -> bool {
+        self.is_mode[v]
+    }
+
+    /// The mode a new vertex of density `level` adjacent to `neighbors` would hang below.
+    ///
+    /// The new vertex comes after every stored vertex of equal density. Only neighbors at or
+    /// above `level` count; `None` means the new vertex would be a mode itself.
+    pub fn attach(&self, neighbors: &[usize], level: f64) -> Option<usize> {
+        attach_below(&self.density, &self.parent, &self.saddle, neighbors, level)
+    }
+
+    pub fn diagram(&self) -> Vec<PersistencePair> {
+        pairs_in_order(&self.density, &self.order, &self.saddle, &self.is_mode)
+    }
+
+    pub fn n_clusters_at(&self, tau: f64) -> Result<usize, TomatoError> {
+        validate_tau(tau)?;
+        let below = self.sorted_prominence.partition_point(|&p| p < tau);
+        Ok(self.sorted_prominence.len() - below)
+    }
+
+    pub fn modes_at(&self, tau: f64) -> Result<Vec<usize>, TomatoError> {
+        validate_tau(tau)?;
+        let mut modes: Vec<usize> = Vec::new();
+        for &v in &self.order {
+            if self.prominence[v] >= tau {
+                modes.push(v);
+            }
+        }
+        Ok(modes)
+    }
+
+    /// Same labels as `tomato_cluster` at `tau`, in O(m α(n)) for m edges.
+    pub fn labels_at(&self, tau: f64) -> Result<Vec<usize>, TomatoError> {
+        Ok(self.merges_at(tau)?.cluster_of)
+    }
+
+    pub(crate) fn merges_at(&self, tau: f64) -> Result<Merges, TomatoError> {
+        validate_tau(tau)?;
+        Ok(protected_merges(&self.graph, &self.density, &self.order, tau))
+    }
+
+    pub fn result_at(&self, tau: f64) -> Result<TomatoResult, TomatoError> {
+        self.result(&TomatoParams::new(tau))
+    }
+
+    /// `params.smoothing` is not applied: the tree is built on the density it was given, so
+    /// smooth that density before `MergeTree::new`.
+    pub fn result(&self, params: &TomatoParams) -> Result<TomatoResult, TomatoError> {
+        params.noise.validate()?;
+        let mut cluster_of = self.labels_at(params.tau)?;
+        let mut modes = self.modes_at(params.tau)?;
+        if !params.noise.is_none() {
+            apply_noise(&params.noise, &self.density, &self.prominence, &mut cluster_of);
+            modes.retain(|&m| cluster_of[m] != NOISE);
+        }
+        Ok(TomatoResult {
+            cluster_of,
+            modes,
+            diagram: self.diagram(),
+        })
+    }
+}
+
+/// The tau = infinity merges of `MergeTree`, over vertices in filtration `order`.
+pub(crate) struct ElderMerges {
+    pub(crate) parent: Vec<usize>,
+    pub(crate) saddle: Vec<Option<usize>>,
+    pub(crate) is_mode: Vec<bool>,
+}
+
+pub(crate) fn elder_merges(graph: &Graph, density: &[f64], order: &[usize]) -> ElderMerges {
+    let n = graph.n();
+    let mut uf = UfTomato::new(n);
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut saddle: Vec<Option<usize>> = vec![None; n];
+    let mut is_mode = vec![false; n];
+
+    let mut uniq_roots: Vec<usize> = Vec::new();
+
+    for &v in order {
+        uf.activate(v);
+
+        uniq_roots.clear();
+        for &u in graph.neighbors(v) {
+            if !uf.is_active(u) {
+                continue;
+            }
+            let ru = uf.find(u);
+            if !uniq_roots.contains(&ru) {
+                uniq_roots.push(ru);
+            }
+        }
+
+        if uniq_roots.is_empty() {
+            is_mode[v] = true;
+            continue;
+        }
+
+        let mut winner_root = uniq_roots[0];
+        let mut winner_mode = uf.mode_of_root(winner_root);
+        for &r in uniq_roots[1..].iter() {
+            let m = uf.mode_of_root(r);
+            if higher(density, m, winner_mode) {
+                winner_root = r;
+                winner_mode = m;
+            }
+        }
+
+        parent[v] = winner_mode;
+        saddle[v] = Some(v);
+        let mut w = uf.union_survivor(density, winner_root, v);
+
+        for &r in uniq_roots.iter() {
+            if r == winner_root {
+                continue;
+            }
+            let m = uf.mode_of_root(r);
+            parent[m] = winner_mode;
+            saddle[m] = Some(v);
+            w = uf.union_survivor(density, w, r);
+        }
+    }
+
+    ElderMerges { parent, saddle, is_mode }
+}
+
+/// One pair per mode, in filtration `order`.
+pub(crate) fn pairs_in_order(
+    density: &[f64],
+    order: &[usize],
+    saddle: &[Option<usize>],
+    is_mode: &[bool],
+) -> Vec<PersistencePair> {
+    let mut diagram: Vec<PersistencePair> = Vec::new();
+    for &m in order {
+        if !is_mode[m] {
+            continue;
+        }
+        diagram.push(PersistencePair {
+            mode: m,
+            birth: density[m],
+            death: saddle[m].map(|s| density[s]),
+            saddle: saddle[m],
+        });
+    }
+    diagram
+}
+
+// The mode a vertex of density `level` adjacent to `neighbors` would hang below, following
+// `parent` through merges at saddles at or above `level`.
+pub(crate) fn attach_below(
+    density: &[f64],
+    parent: &[usize],
+    saddle: &[Option<usize>],
+    neighbors: &[usize],
+    level: f64,
+) -> Option<usize> {
+    let mut winner: Option<usize> = None;
+    for &u in neighbors {
+        if density[u] < level {
+            continue;
+        }
+        let mut top = u;
+        while parent[top] != top {
+            match saddle[top] {
+                Some(s) if density[s] >= level => top = parent[top],
+                _ => break,
+            }
+        }
+        winner = match winner {
+            Some(w) if !higher(density, top, w) => Some(w),
+            _ => Some(top),
+        };
+    }
+    winner
+}
@@ -2,13 +2,29 @@
 
 pub mod backend;
 pub mod graph;
+pub mod hierarchy;
+pub mod metrics;
 pub mod order;
+mod parallel;
+pub mod persistence;
 pub mod pipeline;
+pub mod selection;
+#[cfg(feature = "serde")]
+mod serde_fields;
 pub mod stats;
 pub mod tomato;
 pub mod uf;
 
-pub use backend::{AnnBackend, BruteBackend, HnswBackend, HnswParams};
+pub use backend::{
+    AnnBackend, AxisMetric, BruteBackend, Chebyshev, Cosine, Euclidean, HnswBackend, HnswIndexFile, HnswParams,
+    KdTreeBackend, Mahalanobis, Manhattan, Metric, Minkowski, PrecomputedBackend,
+};
 pub use graph::Graph;
-pub use pipeline::{build_graph, estimate_density, GraphSpec, DensitySpec, PipelineParams, PipelineResult};
-pub use tomato::{tomato_cluster, TomatoError, TomatoParams, TomatoResult};
\ No newline at end of file
+pub use hierarchy::MergeTree;
+pub use persistence::{persistence_diagram, PersistencePair};
+pub use pipeline::{
+    build_graph, estimate_density, GraphSpec, DensitySpec, KnnTable, PipelineParams, PipelineResult, Prediction,
+    TomatoModel,
+};
+pub use selection::{select_tau, TauCriterion, TauSelection};
+pub use tomato::{tomato_cluster, NoisePolicy, TomatoError, TomatoParams, TomatoResult, NOISE};
\ No newline at end of file
@@ -0,0 +1,359 @@
+#![forbid(unsafe_code)]
+
+//! Clustering quality indices.
+//!
+//! External indices compare two labelings of the same points, for example `cluster_of` against
+//! a ground truth, and only look at which points share a label. Internal indices score one
+//! labeling against the geometry of the points. Labels are arbitrary `usize` values; `NOISE`
+//! is handled as set by `NoiseHandling` for external indices and always left out of internal
+//! ones.
+
+use std::collections::HashMap;
+
+use crate::backend::{AnnBackend, Euclidean, Metric};
+use crate::parallel::{Exec, Seq};
+use crate::tomato::{TomatoError, NOISE};
+
+/// How `NOISE` labels enter an external index. Either labeling may contain them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum NoiseHandling {
+    /// Points that are noise in either labeling are dropped.
+    Exclude,
+    /// Every noise point is a cluster of its own.
+    Singletons,
+    /// All noise points form one cluster.
+    Cluster,
+}
+
+/// Homogeneity, completeness and their harmonic mean.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VMeasure {
+    pub homogeneity: f64,
+    pub completeness: f64,
+    pub v_measure: f64,
+}
+
+struct Contingency {
+    n: usize,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    cells: Vec<usize>,
+}
+
+fn dense_ids(labels: impl Iterator<Item = Option<usize>>, ids: &mut Vec<usize>) -> usize {
+    let mut map = HashMap::new();
+    let mut next = 0;
+    for l in labels {
+        let id = match l {
+            Some(l) => *map.entry(l).or_insert_with(|| {
+                next += 1;
+                next - 1
+            }),
+            None => {
+                next += 1;
+                next - 1
+            }
+        };
+        ids.push(id);
+    }
+    next
+}
+
+fn contingency(a: &[usize], b: &[usize], noise: NoiseHandling) -> Result<Contingency, TomatoError> {
+    if a.len() != b.len() {
+        return Err(TomatoError::InvalidLabels(format!(
+            "labelings have {} and {} points",
+            a.len(),
+            b.len()
+        )));
+    }
+    let keep: Vec<usize> = (0..a.len())
+        .filter(|&i| noise != NoiseHandling::Exclude || (a[i] != NOISE && b[i] != NOISE))
+        .collect();
+    // None asks for a fresh id
+    let label = |l: usize| (noise != NoiseHandling::Singletons || l != NOISE).then_some(l);
+
+    let mut ia = Vec::with_capacity(keep.len());
+    let mut ib = Vec::with_capacity(keep.len());
+    let na = dense_ids(keep.iter().map(|&i| label(a[i])), &mut ia);
+    let nb = dense_ids(keep.iter().map(|&i| label(b[i])), &mut ib);
+
+    let mut rows = vec![0; na];
+    let mut cols = vec![0; nb];
+    let mut cells = HashMap::new();
+    for (&x, &y) in ia.iter().zip(&ib) {
+        rows[x] += 1;
+        cols[y] += 1;
+        *cells.entry((x, y)).or_insert(0) += 1;
+    }
+    Ok(Contingency {
+        n: keep.len(),
+        rows,
+        cols,
+        cells: cells.into_values().collect(),
+    })
+}
+
+fn pairs(c: usize) -> f64 {
+    let c = c as f64;
+    c * (c - 1.0) / 2.0
+}
+
+fn entropy(counts: &[usize], n: usize) -> f64 {
+    let n = n as f64;
+    let mut h = 0.0;
+    for &c in counts {
+        if c > 0 {
+            let p = c as f64 / n;
+            h -= p * p.ln();
+        }
+    }
+    h
+}
+
+// Entropies of both labelings and their mutual information, in nats.
+fn information(a: &[usize], b: &[usize], noise: NoiseHandling) -> Result<(f64, f64, f64), TomatoError> {
+    let t = contingency(a, b, noise)?;
+    let ha = entropy(&t.rows, t.n);
+    let hb = entropy(&t.cols, t.n);
+    let hab = entropy(&t.cells, t.n);
+    Ok((ha, hb, (ha + hb - hab).max(0.0)))
+}
+
+/// Adjusted Rand index: the Rand index corrected for chance, 1 for identical partitions and
+/// close to 0 for independent ones. Fewer than two points give 1.
+pub fn adjusted_rand_index(a: &[usize], b: &[usize], noise: NoiseHandling) -> Result<f64, TomatoError> {
+    let t = contingency(a, b, noise)?;
+    if t.n < 2 {
+        return Ok(1.0);
+    }
+    let index: f64 = t.cells.iter().map(|&c| pairs(c)).sum();
+    let sa: f64 = t.rows.iter().map(|&c| pairs(c)).sum();
+    let sb: f64 = t.cols.iter().map(|&c| pairs(c)).sum();
+    let expected = sa * sb / pairs(t.n);
+    let max = 0.5 * (sa + sb);
+    if max == expected {
+        return Ok(1.0);
+    }
+    Ok((index - expected) / (max - expected))
+}
+
+/// Mutual information normalized by the arithmetic mean of the two entropies, in [0, 1]. Two
+/// labelings that each put every point in one cluster give 1.
+pub fn normalized_mutual_info(a: &[usize], b: &[usize], noise: NoiseHandling) -> Result<f64, TomatoError> {
+    let (ha, hb, mi) = information(a, b, noise)?;
+    if ha == 0.0 && hb == 0.0 {
+        return Ok(1.0);
+    }
+    Ok((mi / (0.5 * (ha + hb))).min(1.0))
+}
+
+/// Homogeneity of `pred` with respect to `truth` (each cluster holds one class), completeness
+/// (each class sits in one cluster) and their harmonic mean, which equals the normalized mutual
+/// information.
+pub fn v_measure(truth: &[usize], pred: &[usize], noise: NoiseHandling) -> Result<VMeasure, TomatoError> {
+    let (ht, hp, mi) = information(truth, pred, noise)?;
+    let homogeneity = if ht == 0.0 { 1.0 } else { (mi / ht).min(1.0) };
+    let completeness = if hp == 0.0 { 1.0 } else { (mi / hp).min(1.0) };
+    let v_measure = if homogeneity + completeness == 0.0 {
+        0.0
+    } else {
+        2.0 * homogeneity * completeness / (homogeneity + completeness)
+    };
+    Ok(VMeasure {
+        homogeneity,
+        completeness,
+        v_measure,
+    })
+}
+
+/// Fowlkes–Mallows index: the geometric mean of pair precision and pair recall, in [0, 1].
+pub fn fowlkes_mallows(a: &[usize], b: &[usize], noise: NoiseHandling) -> Result<f64, TomatoError> {
+    let t = contingency(a, b, noise)?;
+    let tk: f64 = t.cells.iter().map(|&c| pairs(c)).sum();
+    let pk: f64 = t.rows.iter().map(|&c| pairs(c)).sum();
+    let qk: f64 = t.cols.iter().map(|&c| pairs(c)).sum();
+    if tk == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(tk / (pk * qk).sqrt())
+}
+
+// Dense cluster ids of the non noise points, and the number of clusters. Internal indices are
+// defined for 2 <= clusters < points.
+fn internal_ids(labels: &[usize], n: usize) -> Result<(Vec<Option<usize>>, usize), TomatoError> {
+    if labels.len() != n {
+        return Err(TomatoError::InvalidLabels(format!(
+            "{} labels for {} points",
+            labels.len(),
+            n
+        )));
+    }
+    let mut map = HashMap::new();
+    let ids: Vec<Option<usize>> = labels
+        .iter()
+        .map(|&l| {
+            (l != NOISE).then(|| {
+                let next = map.len();
+                *map.entry(l).or_insert(next)
+            })
+        })
+        .collect();
+    let k = map.len();
+    let m = ids.iter().flatten().count();
+    if k < 2 || k >= m {
+        return Err(TomatoError::InvalidLabels(format!(
+            "need between 2 and {} clusters, got {}",
+            m.saturating_sub(1),
+            k
+        )));
+    }
+    Ok((ids, k))
+}
+
+/// Mean silhouette over the non noise points, with distances from the backend metric.
+///
+/// For a point with mean distance a to the rest of its cluster and mean distance b to the
+/// nearest other cluster the silhouette is (b - a) / max(a, b), and 0 in a singleton cluster.
+/// Noise points are neither scored nor counted in the means. Distances come from `range_dist2`
+/// with an infinite radius, so approximate backends give an approximate value.
+pub fn silhouette<B: AnnBackend>(backend: &B, labels: &[usize]) -> Result<f64, TomatoError> {
+    let (ids, k) = internal_ids(labels, backend.len())?;
+    let mut size = vec![0usize; k];
+    for c in ids.iter().flatten() {
+        size[*c] += 1;
+    }
+
+    let scores: Vec<Option<f64>> = Seq::map(backend, ids.len(), |b, i| {
+        let ci = ids[i]?;
+        if size[ci] == 1 {
+            return Some(0.0);
+        }
+        let mut sum = vec![0.0; k];
+        let mut count = vec![0usize; k];
+        for (j, d2) in b.range_dist2(i, f64::INFINITY) {
+            if let Some(cj) = ids[j] {
+                sum[cj] += d2.sqrt();
+                count[cj] += 1;
+            }
+        }
+        let a = if count[ci] > 0 { sum[ci] / count[ci] as f64 } else { 0.0 };
+        let mut b = f64::INFINITY;
+        for c in 0..k {
+            if c != ci && count[c] > 0 {
+                b = b.min(sum[c] / count[c] as f64);
+            }
+        }
+        let s = if !b.is_finite() || a.max(b) == 0.0 {
+            0.0
+        } else {
+            (b - a) / a.max(b)
+        };
+        Some(s)
+    });
+
+    let scored: Vec<f64> = scores.into_iter().flatten().collect();
+    Ok(scored.iter().sum::<f64>() / scored.len() as f64)
+}
+
+fn validate_points(points: &[Vec<f64>]) -> Result<usize, TomatoError> {
+    let d = points.first().map_or(0, |p| p.len());
+    for (i, p) in points.iter().enumerate() {
+        if p.len() != d {
+            return Err(TomatoError::QueryDimensionMismatch {
+                expected: d,
+                got: p.len(),
+            });
+        }
+        if p.iter().any(|x| !x.is_finite()) {
+            return Err(TomatoError::NonFiniteQuery(i));
+        }
+    }
+    Ok(d)
+}
+
+// Cluster sizes and centroids of the non noise points, and their overall mean.
+fn centroids(points: &[Vec<f64>], ids: &[Option<usize>], k: usize, d: usize) -> (Vec<usize>, Vec<Vec<f64>>, Vec<f64>) {
+    let mut size = vec![0usize; k];
+    let mut cen = vec![vec![0.0; d]; k];
+    let mut mean = vec![0.0; d];
+    let mut m = 0usize;
+    for (p, id) in points.iter().zip(ids) {
+        if let Some(c) = *id {
+            size[c] += 1;
+            m += 1;
+            for j in 0..d {
+                cen[c][j] += p[j];
+                mean[j] += p[j];
+            }
+        }
+    }
+    for (row, &s) in cen.iter_mut().zip(&size) {
+        for x in row.iter_mut() {
+            *x /= s as f64;
+        }
+    }
+    for x in mean.iter_mut() {
+        *x /= m as f64;
+    }
+    (size, cen, mean)
+}
+
+/// Davies–Bouldin index over the non noise points, with Euclidean distances to the cluster
+/// centroids. Lower is better, 0 is the minimum.
+pub fn davies_bouldin(points: &[Vec<f64>], labels: &[usize]) -> Result<f64, TomatoError> {
+    let d = validate_points(points)?;
+    let (ids, k) = internal_ids(labels, points.len())?;
+    let (size, cen, _) = centroids(points, &ids, k, d);
+
+    let mut scatter = vec![0.0; k];
+    for (p, id) in points.iter().zip(&ids) {
+        if let Some(c) = *id {
+            scatter[c] += Euclidean.dist2(p, &cen[c]).sqrt();
+        }
+    }
+    for c in 0..k {
+        scatter[c] /= size[c] as f64;
+    }
+
+    let mut total = 0.0;
+    for a in 0..k {
+        let mut worst = 0.0f64;
+        for b in 0..k {
+            let sep = Euclidean.dist2(&cen[a], &cen[b]).sqrt();
+            if b != a && sep > 0.0 {
+                worst = worst.max((scatter[a] + scatter[b]) / sep);
+            }
+        }
+        total += worst;
+    }
+    Ok(total / k as f64)
+}
+
+/// Calinski–Harabasz index over the non noise points: between cluster over within cluster
+/// dispersion, each divided by its degrees of freedom. Higher is better. All clusters reduced to
+/// their centroid give 1.
+pub fn calinski_harabasz(points: &[Vec<f64>], labels: &[usize]) -> Result<f64, TomatoError> {
+    let d = validate_points(points)?;
+    let (ids, k) = internal_ids(labels, points.len())?;
+    let (size, cen, mean) = centroids(points, &ids, k, d);
+    let m: usize = size.iter().sum();
+
+    let mut between = 0.0;
+    for c in 0..k {
+        between += size[c] as f64 * Euclidean.dist2(&cen[c], &mean);
+    }
+    let mut within = 0.0;
+    for (p, id) in points.iter().zip(&ids) {
+        if let Some(c) = *id {
+            within += Euclidean.dist2(p, &cen[c]);
+        }
+    }
+    if within == 0.0 {
+        return Ok(1.0);
+    }
+    Ok(between * (m - k) as f64 / (within * (k - 1) as f64))
+}
@@ -0,0 +1,50 @@
+#![forbid(unsafe_code)]
+
+// Per point work of the pipeline. `Seq` runs it in a plain loop and needs nothing of the backend;
+// `Par`, with the parallel feature, spreads the points over the rayon pool and is only reached
+// through the `_par` entry points, which ask for a `Sync` backend. Results are collected in index
+// order and each point is computed by the same closure, so both give identical output.
+
+pub(crate) trait Exec<B: ?Sized> {
+    fn map<T, F>(backend: &B, n: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&B, usize) -> T + Sync + Send;
+
+    /// Like `map`, returning the error of the smallest failing index.
+    fn try_map<T, E, F>(backend: &B, n: usize, f: F) -> Result<Vec<T>, E>
+    where
+        T: Send,
+        E: Send,
+        F: Fn(&B, usize) -> Result<T, E> + Sync + Send,
+    {
+        Self::map(backend, n, f).into_iter().collect()
+    }
+}
+
+pub(crate) struct Seq;
+
+impl<B: ?Sized> Exec<B> for Seq {
+    fn map<T, F>(backend: &B, n: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&B, usize) -> T + Sync + Send,
+    {
+        (0..n).map(|i| f(backend, i)).collect()
+    }
+}
+
+#[cfg(feature = "parallel")]
+pub(crate) struct Par;
+
+#[cfg(feature = "parallel")]
+impl<B: Sync + ?Sized> Exec<B> for Par {
+    fn map<T, F>(backend: &B, n: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&B, usize) -> T + Sync + Send,
+    {
+        use rayon::prelude::*;
+        (0..n).into_par_iter().map(|i| f(backend, i)).collect()
+    }
+}
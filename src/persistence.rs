@@ -0,0 +1,56 @@
+#![forbid(unsafe_code)]
+
+use crate::graph::Graph;
+use crate::hierarchy::{elder_merges, pairs_in_order};
+use crate::order::vertices_desc_by_density;
+use crate::tomato::{validate_density, TomatoError};
+
+/// One point of the 0 dimensional persistence diagram of the vertex superlevel filtration.
+///
+/// `death` and `saddle` are `None` for essential classes, that is modes whose component never
+/// merges into a component with a higher mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PersistencePair {
+    pub mode: usize,
+    pub birth: f64,
+    pub death: Option<f64>,
+    pub saddle: Option<usize>,
+}
+
+impl PersistencePair {
+    #[inline]
+    pub fn is_essential(&self) -> bool {
+        self.death.is_none()
+    }
+
+    /// Birth minus death, infinite for essential classes.
+    #[inline]
+    pub fn prominence(&self) -> f64 {
+        match self.death {
+            Some(d) => self.birth - d,
+            None => f64::INFINITY,
+        }
+    }
+}
+
+/// Computes the diagram with tau = infinity semantics: every merge follows the elder rule, the
+/// component with the lower mode dies at the density of the vertex that connects it.
+///
+/// Pairs are returned in mode order, higher birth first, then smaller vertex id.
+pub fn persistence_diagram(
+    graph: &Graph,
+    density: &[f64],
+) -> Result<Vec<PersistencePair>, TomatoError> {
+    validate_density(density)?;
+    if density.len() != graph.n() {
+        return Err(TomatoError::DensityLengthMismatch);
+    }
+    Ok(diagram_in_order(graph, density, &vertices_desc_by_density(density)))
+}
+
+// `persistence_diagram` over vertices already in filtration order.
+pub(crate) fn diagram_in_order(graph: &Graph, density: &[f64], order: &[usize]) -> Vec<PersistencePair> {
+    let merges = elder_merges(graph, density, order);
+    pairs_in_order(density, order, &merges.saddle, &merges.is_mode)
+}
@@ -0,0 +1,246 @@
+#![forbid(unsafe_code)]
+
+use crate::backend::AnnBackend;
+use crate::parallel::{Exec, Seq};
+use crate::pipeline::knn::KnnTable;
+use crate::tomato::TomatoError;
+
+/// What a selector looked at to pick its bandwidth.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BandwidthDiagnostics {
+    /// sigma is the spread of the data, the bandwidth is sigma times factor.
+    Scott { n: usize, dim: usize, sigma: f64, factor: f64 },
+    Silverman { n: usize, dim: usize, sigma: f64, factor: f64 },
+    /// Squared distance from each point to its k-th nearest neighbor: smallest, median, largest.
+    KnnDistance { k: usize, min: f64, median: f64, max: f64 },
+    /// Mean leave-one-out log likelihood of each candidate, the index of the best one, and
+    /// whether it is the smallest or largest candidate, in which case the grid should be widened.
+    LooLikelihood { scores: Vec<(f64, f64)>, best: usize, on_boundary: bool },
+}
+
+/// A selected `bandwidth2`, the sigma squared of the Gaussian kernel, and its diagnostics.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandwidthChoice {
+    pub bandwidth2: f64,
+    pub diagnostics: BandwidthDiagnostics,
+}
+
+fn bandwidth_error(msg: &str) -> TomatoError {
+    TomatoError::Bandwidth(msg.to_string())
+}
+
+fn validate_points(points: &[Vec<f64>]) -> Result<(usize, usize), TomatoError> {
+    let n = points.len();
+    if n < 2 {
+        return Err(bandwidth_error("need at least 2 points"));
+    }
+    let d = points[0].len();
+    if d == 0 {
+        return Err(bandwidth_error("points have dimension 0"));
+    }
+    for (i, p) in points.iter().enumerate() {
+        if p.len() != d {
+            return Err(TomatoError::QueryDimensionMismatch {
+                expected: d,
+                got: p.len(),
+            });
+        }
+        if p.iter().any(|x| !x.is_finite()) {
+            return Err(TomatoError::NonFiniteQuery(i));
+        }
+    }
+    Ok((n, d))
+}
+
+// Sample standard deviation of one coordinate, and its interquartile range.
+fn spread(points: &[Vec<f64>], j: usize) -> (f64, f64) {
+    let n = points.len() as f64;
+    let mean = points.iter().map(|p| p[j]).sum::<f64>() / n;
+    let var = points.iter().map(|p| (p[j] - mean) * (p[j] - mean)).sum::<f64>() / (n - 1.0);
+
+    let mut xs: Vec<f64> = points.iter().map(|p| p[j]).collect();
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    (var.sqrt(), quantile(&xs, 0.75) - quantile(&xs, 0.25))
+}
+
+// Linear interpolation between order statistics of sorted values.
+pub(crate) fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    sorted[lo] + (pos - lo as f64) * (sorted[hi] - sorted[lo])
+}
+
+// Root mean square of per coordinate spreads, so that the isotropic kernel sees the average
+// variance.
+fn rms(s: &[f64]) -> f64 {
+    (s.iter().map(|x| x * x).sum::<f64>() / s.len() as f64).sqrt()
+}
+
+/// Scott's rule: sigma n^(-1/(d+4)), with sigma the root mean square of the per coordinate
+/// standard deviations.
+pub fn scott_bandwidth2(points: &[Vec<f64>]) -> Result<BandwidthChoice, TomatoError> {
+    let (n, d) = validate_points(points)?;
+    let s: Vec<f64> = (0..d).map(|j| spread(points, j).0).collect();
+    let sigma = rms(&s);
+    if sigma.is_nan() || sigma <= 0.0 {
+        return Err(bandwidth_error("points have zero spread"));
+    }
+    let factor = (n as f64).powf(-1.0 / (d as f64 + 4.0));
+    Ok(BandwidthChoice {
+        bandwidth2: (sigma * factor) * (sigma * factor),
+        diagnostics: BandwidthDiagnostics::Scott { n, dim: d, sigma, factor },
+    })
+}
+
+/// Silverman's rule: sigma (4 / ((d+2) n))^(1/(d+4)), with the robust per coordinate spread
+/// min(standard deviation, IQR / 1.349), which resists outliers and heavy tails.
+pub fn silverman_bandwidth2(points: &[Vec<f64>]) -> Result<BandwidthChoice, TomatoError> {
+    let (n, d) = validate_points(points)?;
+    let s: Vec<f64> = (0..d)
+        .map(|j| {
+            let (sd, iqr) = spread(points, j);
+            if iqr > 0.0 {
+                sd.min(iqr / 1.349)
+            } else {
+                sd
+            }
+        })
+        .collect();
+    let sigma = rms(&s);
+    if sigma.is_nan() || sigma <= 0.0 {
+        return Err(bandwidth_error("points have zero spread"));
+    }
+    let factor = (4.0 / ((d as f64 + 2.0) * n as f64)).powf(1.0 / (d as f64 + 4.0));
+    Ok(BandwidthChoice {
+        bandwidth2: (sigma * factor) * (sigma * factor),
+        diagnostics: BandwidthDiagnostics::Silverman { n, dim: d, sigma, factor },
+    })
+}
+
+/// The median over all points of the squared distance to the k-th nearest neighbor, in the
+/// backend metric. With k around the k of `KdeGaussianKnn`, the kernel then still weighs the
+/// far end of a typical neighbor list.
+pub fn knn_bandwidth2<B: AnnBackend>(backend: &B, k: usize) -> Result<BandwidthChoice, TomatoError> {
+    if k == 0 {
+        return Err(bandwidth_error("k must be >= 1"));
+    }
+    if backend.len() < 2 {
+        return Err(bandwidth_error("need at least 2 points"));
+    }
+    let knn = KnnTable::build(backend, k);
+    let mut kth: Vec<f64> = (0..knn.n()).filter_map(|i| knn.row(i).last().map(|x| x.1)).collect();
+    if kth.is_empty() {
+        return Err(bandwidth_error("backend returned no neighbors"));
+    }
+    kth.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = quantile(&kth, 0.5);
+    if median.is_nan() || median <= 0.0 {
+        return Err(bandwidth_error("median neighbor distance is zero, points are duplicated"));
+    }
+    Ok(BandwidthChoice {
+        bandwidth2: median,
+        diagnostics: BandwidthDiagnostics::KnnDistance {
+            k,
+            min: kth[0],
+            median,
+            max: kth[kth.len() - 1],
+        },
+    })
+}
+
+/// `count` values from `lo` to `hi` with a constant ratio, a candidate grid for
+/// `loo_bandwidth2`, for example around a rule of thumb. A count of 1 gives `lo` alone and 0
+/// an empty grid.
+pub fn log_spaced(lo: f64, hi: f64, count: usize) -> Vec<f64> {
+    match count {
+        0 => return Vec::new(),
+        1 => return vec![lo],
+        _ => {}
+    }
+    let step = (hi / lo).ln() / (count - 1) as f64;
+    (0..count).map(|i| lo * (step * i as f64).exp()).collect()
+}
+
+/// Leave-one-out likelihood cross validation over `candidates`.
+///
+/// Each candidate is scored by the mean over points of the log of the normalized Gaussian KDE
+/// at that point built from all other points, and the best one wins. With `k` set, each point
+/// only sums over its k nearest neighbors, as `KdeGaussianKnn` does; with `None`, over all
+/// points, as `KdeGaussianFullBrute`, at O(n²) cost. The normalization needs the dimension, so
+/// a `PrecomputedBackend` must be given an intrinsic dimension.
+pub fn loo_bandwidth2<B: AnnBackend>(
+    backend: &B,
+    candidates: &[f64],
+    k: Option<usize>,
+) -> Result<BandwidthChoice, TomatoError> {
+    let n = backend.len();
+    let d = backend.dim();
+    if n < 2 {
+        return Err(bandwidth_error("need at least 2 points"));
+    }
+    if d == 0 {
+        return Err(bandwidth_error("backend dimension is 0, set an intrinsic dimension"));
+    }
+    if candidates.is_empty() {
+        return Err(bandwidth_error("no candidate bandwidth"));
+    }
+    if candidates.iter().any(|&h2| !h2.is_finite() || h2 <= 0.0) {
+        return Err(bandwidth_error("candidates must be finite and > 0"));
+    }
+
+    let nbrs: Vec<Vec<f64>> = match k {
+        Some(0) => return Err(bandwidth_error("k must be >= 1")),
+        Some(k) => {
+            let knn = KnnTable::build(backend, k);
+            knn.into_rows()
+                .into_iter()
+                .map(|row| row.into_iter().map(|x| x.1).collect())
+                .collect()
+        }
+        None => Seq::map(backend, n, |b, i| {
+            b.range_dist2(i, f64::INFINITY)
+                .into_iter()
+                .map(|x| x.1)
+                .collect()
+        }),
+    };
+
+    let log_norm = |h2: f64| -0.5 * d as f64 * (2.0 * std::f64::consts::PI * h2).ln() - ((n - 1) as f64).ln();
+    let mut scores = Vec::with_capacity(candidates.len());
+    for &h2 in candidates {
+        let inv = 1.0 / (2.0 * h2);
+        let mut total = 0.0;
+        for row in &nbrs {
+            // log sum exp, shifted by the nearest neighbor so the sum never underflows to zero
+            let m = row.iter().cloned().fold(f64::INFINITY, f64::min);
+            let s: f64 = row.iter().map(|&d2| (-(d2 - m) * inv).exp()).sum();
+            total += if row.is_empty() { f64::NEG_INFINITY } else { s.ln() - m * inv };
+        }
+        scores.push((h2, total / n as f64 + log_norm(h2)));
+    }
+
+    let mut best = 0;
+    for i in 1..scores.len() {
+        if scores[i].1 > scores[best].1 {
+            best = i;
+        }
+    }
+    if !scores[best].1.is_finite() {
+        return Err(bandwidth_error("some point has no neighbors"));
+    }
+    let lo = candidates.iter().cloned().fold(f64::INFINITY, f64::min);
+    let hi = candidates.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let h2 = scores[best].0;
+    Ok(BandwidthChoice {
+        bandwidth2: h2,
+        diagnostics: BandwidthDiagnostics::LooLikelihood {
+            scores,
+            best,
+            on_boundary: candidates.len() > 1 && (h2 == lo || h2 == hi),
+        },
+    })
+}
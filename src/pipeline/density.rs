@@ -1,14 +1,29 @@
 #![forbid(unsafe_code)]
 
 use crate::backend::AnnBackend;
-use crate::tomato::TomatoError;
+#[cfg(feature = "parallel")]
+use crate::parallel::Par;
+use crate::parallel::{Exec, Seq};
+use crate::pipeline::knn::KnnTable;
+use crate::tomato::{validate_weights, TomatoError};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum DensitySpec {
     KnnLog {
         k: usize,
         eps: f64,
     },
+    /// Distance to measure: with m the mean of the p-th powers of the k nearest distances,
+    /// the density is -d ln(sqrt(m^(2/p) + eps)), as `KnnLog` with the farthest distance
+    /// replaced by this power mean. p = 2 is the usual root mean square.
+    Dtm {
+        k: usize,
+        #[cfg_attr(feature = "serde", serde(default = "default_dtm_p"))]
+        p: f64,
+        eps: f64,
+    },
     KdeGaussianKnn {
         k: usize,
         bandwidth2: f64,
@@ -16,64 +31,462 @@ pub enum DensitySpec {
     KdeGaussianFullBrute {
         bandwidth2: f64,
     },
+    /// Sum of `kernel` over the k nearest neighbors.
+    KdeKnn {
+        kernel: Kernel,
+        k: usize,
+        bandwidth2: f64,
+    },
+    /// Sum of `kernel` over every point in its support, found by range search, so compact
+    /// kernels sum exactly. The other kernels sum over all points, at O(n²) cost.
+    KdeRange {
+        kernel: Kernel,
+        bandwidth2: f64,
+    },
+    /// Gaussian KDE over the k nearest neighbors with a bandwidth per point, see `AdaptiveKde`.
+    KdeGaussianAdaptive {
+        k: usize,
+        bandwidth2: f64,
+        method: AdaptiveKde,
+    },
 }
 
-pub fn estimate_density<B: AnnBackend>(backend: &B, spec: DensitySpec) -> Result<Vec<f64>, TomatoError> {
-    let n = backend.len();
-    let d = backend.dim();
+/// Kernel profiles of `KdeKnn` and `KdeRange`, as functions of u = distance / h with
+/// h² = `bandwidth2`. Like the Gaussian KDE, sums are not normalized, which leaves the
+/// clustering unchanged for a fixed kernel and bandwidth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Kernel {
+    /// exp(-u² / 2), as `KdeGaussianKnn`.
+    Gaussian,
+    /// 1 - u² for u <= 1.
+    Epanechnikov,
+    /// 1 for u <= 1, the number of points within distance h.
+    Tophat,
+    /// (1 - u²)³ for u <= 1.
+    Triweight,
+    /// exp(-u), also called exponential.
+    Laplacian,
+    /// 1 / (1 + u²).
+    Cauchy,
+}
 
-    match spec {
-        DensitySpec::KnnLog { k, eps } => {
+impl Kernel {
+    /// Whether the kernel is zero for u > 1.
+    pub fn is_compact(&self) -> bool {
+        matches!(self, Kernel::Epanechnikov | Kernel::Tophat | Kernel::Triweight)
+    }
+
+    /// The kernel at u² = `u2`.
+    pub fn weight(&self, u2: f64) -> f64 {
+        if self.is_compact() && u2 > 1.0 {
+            return 0.0;
+        }
+        match self {
+            Kernel::Gaussian => (-0.5 * u2).exp(),
+            Kernel::Epanechnikov => 1.0 - u2,
+            Kernel::Tophat => 1.0,
+            Kernel::Triweight => (1.0 - u2).powi(3),
+            Kernel::Laplacian => (-u2.sqrt()).exp(),
+            Kernel::Cauchy => 1.0 / (1.0 + u2),
+        }
+    }
+}
+
+/// Where the bandwidth of `KdeGaussianAdaptive` varies.
+///
+/// Each point takes the pilot density of its k-th neighbor distance r, proportional to r^-d,
+/// and the bandwidth of Abramson's square root law, h = h0 (r / h0)^(d/2) with h0² the
+/// `bandwidth2` of the spec, so a point whose k-th neighbor lies at h0 keeps h0 and sparse
+/// regions get wider kernels. Kernels are weighed by (h0 / h)^d so that densities at different
+/// bandwidths compare, and a constant h0 gives back `KdeGaussianKnn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AdaptiveKde {
+    /// One bandwidth per evaluation point, from its own k-th neighbor distance.
+    Balloon,
+    /// One bandwidth per sample point, from the k-th neighbor distance of each neighbor.
+    SamplePoint,
+}
+
+#[cfg(feature = "serde")]
+fn default_dtm_p() -> f64 {
+    2.0
+}
+
+impl DensitySpec {
+    /// The number of neighbors the spec reads from a kNN table, `None` for full sums.
+    pub fn knn_k(&self) -> Option<usize> {
+        match *self {
+            DensitySpec::KnnLog { k, .. }
+            | DensitySpec::Dtm { k, .. }
+            | DensitySpec::KdeGaussianKnn { k, .. }
+            | DensitySpec::KdeKnn { k, .. }
+            | DensitySpec::KdeGaussianAdaptive { k, .. } => Some(k),
+            DensitySpec::KdeGaussianFullBrute { .. } | DensitySpec::KdeRange { .. } => None,
+        }
+    }
+
+    pub(crate) fn validate<B: AnnBackend>(&self, backend: &B) -> Result<(), TomatoError> {
+        validate_spec(self)?;
+        validate_dim(self, backend.len(), backend.dim())
+    }
+
+    // Squared search radius of the specs that are not kNN based, infinite for kernels without
+    // compact support.
+    fn range_radius2(&self) -> f64 {
+        match *self {
+            DensitySpec::KdeRange { kernel, bandwidth2 } if kernel.is_compact() => bandwidth2,
+            _ => f64::INFINITY,
+        }
+    }
+}
+
+fn validate_spec(spec: &DensitySpec) -> Result<(), TomatoError> {
+    match *spec {
+        DensitySpec::KnnLog { k, .. } => {
             if k == 0 {
                 return Err(TomatoError::InvalidGraph("k must be >= 1".to_string()));
             }
-            let eps = eps.max(0.0);
-            let knn = backend.knn_all_indices_dist2(k);
-            let mut out = vec![0.0; n];
-            for i in 0..n {
-                let mut max_d2 = 0.0;
-                for &(_j, d2) in &knn[i] {
-                    if d2 > max_d2 {
-                        max_d2 = d2;
-                    }
-                }
-                let r = (max_d2 + eps).sqrt();
-                let logr = r.ln();
-                out[i] = - (d as f64) * logr;
+        }
+        DensitySpec::Dtm { k, p, .. } => {
+            if k == 0 {
+                return Err(TomatoError::InvalidGraph("k must be >= 1".to_string()));
+            }
+            if !p.is_finite() || p <= 0.0 {
+                return Err(TomatoError::InvalidGraph("p must be finite and > 0".to_string()));
             }
-            Ok(out)
         }
-        DensitySpec::KdeGaussianKnn { k, bandwidth2 } => {
-            if !(bandwidth2 > 0.0) {
+        DensitySpec::KdeGaussianKnn { bandwidth2, .. } | DensitySpec::KdeGaussianFullBrute { bandwidth2 } => {
+            if bandwidth2.is_nan() || bandwidth2 <= 0.0 {
                 return Err(TomatoError::InvalidGraph("bandwidth2 must be > 0".to_string()));
             }
-            let knn = backend.knn_all_indices_dist2(k);
-            let inv = 1.0 / (2.0 * bandwidth2);
-            let mut out = vec![0.0; n];
-            for i in 0..n {
-                let mut s = 0.0;
-                for &(_j, d2) in &knn[i] {
-                    s += (-d2 * inv).exp();
+        }
+        DensitySpec::KdeKnn { k, bandwidth2, .. } | DensitySpec::KdeGaussianAdaptive { k, bandwidth2, .. } => {
+            if k == 0 {
+                return Err(TomatoError::InvalidGraph("k must be >= 1".to_string()));
+            }
+            if !bandwidth2.is_finite() || bandwidth2 <= 0.0 {
+                return Err(TomatoError::InvalidGraph("bandwidth2 must be finite and > 0".to_string()));
+            }
+        }
+        DensitySpec::KdeRange { bandwidth2, .. } => {
+            if !bandwidth2.is_finite() || bandwidth2 <= 0.0 {
+                return Err(TomatoError::InvalidGraph("bandwidth2 must be finite and > 0".to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_dim(spec: &DensitySpec, n: usize, d: usize) -> Result<(), TomatoError> {
+    let name = match spec {
+        DensitySpec::KnnLog { .. } => "KnnLog",
+        DensitySpec::Dtm { .. } => "Dtm",
+        DensitySpec::KdeGaussianAdaptive { .. } => "KdeGaussianAdaptive",
+        _ => return Ok(()),
+    };
+    if d == 0 && n > 0 {
+        return Err(TomatoError::InvalidGraph(format!(
+            "{} needs a backend dimension >= 1, set an intrinsic dimension",
+            name
+        )));
+    }
+    Ok(())
+}
+
+// Largest squared distance in a neighbor list, that of the k-th neighbor, 0 for an empty list.
+fn kth_dist2(nbrs: &[(usize, f64)]) -> f64 {
+    nbrs.iter().fold(0.0, |m, x| if x.1 > m { x.1 } else { m })
+}
+
+// Log of the adaptive kernel weight (h0 / h)^d exp(-d2 / (2 h²)), with h from the k-th neighbor
+// distance r2 of the point that owns the kernel. Computed in logs so that r2 = 0, from
+// duplicated points, gives weight 0 away from the point and an infinite density on it.
+fn adaptive_log_weight(bandwidth2: f64, d: usize, r2: f64, d2: f64) -> f64 {
+    let half_d = 0.5 * d as f64;
+    let h2 = (bandwidth2 * (r2 / bandwidth2).powf(half_d)).max(f64::MIN_POSITIVE);
+    let decay = if d2 > 0.0 { d2 / (2.0 * h2) } else { 0.0 };
+    half_d * (bandwidth2 / h2).ln() - decay
+}
+
+// ln of the mean weight of a neighbor list, the mass term of the weighted kNN estimators, 0
+// without weights.
+fn log_mean_weight(nbrs: &[(usize, f64)], weights: Option<&[f64]>) -> f64 {
+    match weights {
+        Some(w) if !nbrs.is_empty() => (nbrs.iter().map(|&(j, _)| w[j]).sum::<f64>() / nbrs.len() as f64).ln(),
+        _ => 0.0,
+    }
+}
+
+// Whether the mass term of the weighted `KnnLog` and `Dtm` is finite, that is whether some
+// neighbor weighs more than 0. Always true for the kernel sums, where zero mass is density 0.
+fn has_mass(spec: &DensitySpec, nbrs: &[(usize, f64)], weights: Option<&[f64]>) -> bool {
+    match (spec, weights) {
+        (DensitySpec::KnnLog { .. } | DensitySpec::Dtm { .. }, Some(w)) => {
+            nbrs.is_empty() || nbrs.iter().any(|&(j, _)| w[j] > 0.0)
+        }
+        _ => true,
+    }
+}
+
+// Density of one point from its neighbor list, shared by the stored points and new queries.
+// `kth` gives the k-th neighbor squared distance of a stored point, read only by the sample
+// point estimator. `weights` multiply the contribution of each neighbor.
+fn density_from_neighbors(
+    spec: &DensitySpec,
+    d: usize,
+    nbrs: &[(usize, f64)],
+    kth: impl Fn(usize) -> f64,
+    weights: Option<&[f64]>,
+) -> f64 {
+    let w = |j: usize| weights.map_or(1.0, |w| w[j]);
+    match *spec {
+        DensitySpec::KnnLog { eps, .. } => {
+            let eps = eps.max(0.0);
+            let mut max_d2 = 0.0;
+            for &(_j, d2) in nbrs {
+                if d2 > max_d2 {
+                    max_d2 = d2;
                 }
-                out[i] = s;
             }
-            Ok(out)
+            let r = (max_d2 + eps).sqrt();
+            let logr = r.ln();
+            - (d as f64) * logr + log_mean_weight(nbrs, weights)
         }
-        DensitySpec::KdeGaussianFullBrute { bandwidth2 } => {
-            if !(bandwidth2 > 0.0) {
-                return Err(TomatoError::InvalidGraph("bandwidth2 must be > 0".to_string()));
+        DensitySpec::Dtm { p, eps, .. } => {
+            let mut m = 0.0;
+            let mut mass = 0.0;
+            for &(j, d2) in nbrs {
+                m += w(j) * d2.powf(0.5 * p);
+                mass += w(j);
             }
+            if mass > 0.0 {
+                m /= mass;
+            }
+            let dtm2 = m.powf(2.0 / p);
+            -0.5 * (d as f64) * (dtm2 + eps.max(0.0)).ln() + log_mean_weight(nbrs, weights)
+        }
+        DensitySpec::KdeGaussianKnn { bandwidth2, .. } | DensitySpec::KdeGaussianFullBrute { bandwidth2 } => {
             let inv = 1.0 / (2.0 * bandwidth2);
-            let mut out = vec![0.0; n];
-            for i in 0..n {
-                let nbrs = backend.knn_indices_dist2(i, n.saturating_sub(1));
-                let mut s = 0.0;
-                for &(_j, d2) in &nbrs {
-                    s += (-d2 * inv).exp();
-                }
-                out[i] = s;
+            let mut s = 0.0;
+            for &(j, d2) in nbrs {
+                s += w(j) * (-d2 * inv).exp();
             }
-            Ok(out)
+            s
         }
+        DensitySpec::KdeKnn { kernel, bandwidth2, .. } | DensitySpec::KdeRange { kernel, bandwidth2 } => {
+            nbrs.iter().map(|&(j, d2)| w(j) * kernel.weight(d2 / bandwidth2)).sum()
+        }
+        DensitySpec::KdeGaussianAdaptive { bandwidth2, method, .. } => match method {
+            AdaptiveKde::Balloon => {
+                let r2 = kth_dist2(nbrs);
+                nbrs.iter()
+                    .map(|&(j, d2)| w(j) * adaptive_log_weight(bandwidth2, d, r2, d2).exp())
+                    .sum()
+            }
+            AdaptiveKde::SamplePoint => nbrs
+                .iter()
+                .map(|&(j, d2)| w(j) * adaptive_log_weight(bandwidth2, d, kth(j), d2).exp())
+                .sum(),
+        },
     }
-}
\ No newline at end of file
+}
+
+pub fn estimate_density<B: AnnBackend>(backend: &B, spec: DensitySpec) -> Result<Vec<f64>, TomatoError> {
+    validate_spec(&spec)?;
+    validate_dim(&spec, backend.len(), backend.dim())?;
+    let knn = spec.knn_k().map(|k| KnnTable::build(backend, k));
+    estimate::<B, Seq>(backend, spec, knn.as_ref(), None)
+}
+
+/// Same as `estimate_density`, with the per point queries and sums spread over the rayon pool.
+#[cfg(feature = "parallel")]
+pub fn estimate_density_par<B: AnnBackend + Sync>(backend: &B, spec: DensitySpec) -> Result<Vec<f64>, TomatoError> {
+    validate_spec(&spec)?;
+    validate_dim(&spec, backend.len(), backend.dim())?;
+    let knn = spec.knn_k().map(|k| KnnTable::build_par(backend, k));
+    estimate::<B, Par>(backend, spec, knn.as_ref(), None)
+}
+
+/// Same as `estimate_density` with a weight per stored point, for example the multiplicity of
+/// a pre-aggregated row, that multiplies its contribution to the density of its neighbors.
+///
+/// Kernel sums weigh each kernel. `KnnLog` and `Dtm` weigh the distances of the power mean and
+/// add the log of the mean neighbor weight, the mass of the ball, so that unit weights give
+/// the unweighted density; a point whose neighbors all weigh zero has no finite density and is
+/// an `InvalidWeights` error.
+/// As without weights, a point does not count toward its own density.
+pub fn estimate_density_weighted<B: AnnBackend>(
+    backend: &B,
+    spec: DensitySpec,
+    weights: &[f64],
+) -> Result<Vec<f64>, TomatoError> {
+    validate_spec(&spec)?;
+    validate_dim(&spec, backend.len(), backend.dim())?;
+    validate_weights(weights, backend.len())?;
+    let knn = spec.knn_k().map(|k| KnnTable::build(backend, k));
+    estimate::<B, Seq>(backend, spec, knn.as_ref(), Some(weights))
+}
+
+/// Same as `estimate_density`, with kNN specs answered from `knn` instead of new backend
+/// queries.
+pub fn estimate_density_with_knn<B: AnnBackend>(
+    backend: &B,
+    spec: DensitySpec,
+    knn: &KnnTable,
+) -> Result<Vec<f64>, TomatoError> {
+    estimate_with_knn::<B, Seq>(backend, spec, knn, None)
+}
+
+/// Same as `estimate_density_weighted`, with kNN specs answered from `knn`.
+pub fn estimate_density_with_knn_weighted<B: AnnBackend>(
+    backend: &B,
+    spec: DensitySpec,
+    knn: &KnnTable,
+    weights: &[f64],
+) -> Result<Vec<f64>, TomatoError> {
+    estimate_with_knn::<B, Seq>(backend, spec, knn, Some(weights))
+}
+
+pub(crate) fn estimate_with_knn<B: AnnBackend, X: Exec<B>>(
+    backend: &B,
+    spec: DensitySpec,
+    knn: &KnnTable,
+    weights: Option<&[f64]>,
+) -> Result<Vec<f64>, TomatoError> {
+    validate_spec(&spec)?;
+    validate_dim(&spec, backend.len(), backend.dim())?;
+    if let Some(w) = weights {
+        validate_weights(w, backend.len())?;
+    }
+    if let Some(k) = spec.knn_k() {
+        knn.check(backend, k)?;
+    }
+    estimate::<B, X>(backend, spec, Some(knn), weights)
+}
+
+// `knn` is Some whenever the spec is kNN based.
+fn estimate<B: AnnBackend, X: Exec<B>>(
+    backend: &B,
+    spec: DensitySpec,
+    knn: Option<&KnnTable>,
+    weights: Option<&[f64]>,
+) -> Result<Vec<f64>, TomatoError> {
+    let n = backend.len();
+    let d = backend.dim();
+
+    match spec {
+        DensitySpec::KnnLog { k, .. }
+        | DensitySpec::Dtm { k, .. }
+        | DensitySpec::KdeGaussianKnn { k, .. }
+        | DensitySpec::KdeKnn { k, .. }
+        | DensitySpec::KdeGaussianAdaptive { k, .. } => {
+            let knn = knn.unwrap();
+            if let Some(i) = (0..n).find(|&i| !has_mass(&spec, knn.neighbors(i, k), weights)) {
+                return Err(TomatoError::InvalidWeights(format!(
+                    "the {} nearest neighbors of point {} all weigh 0",
+                    k, i
+                )));
+            }
+            let kth = |j: usize| kth_dist2(knn.neighbors(j, k));
+            Ok(X::map(backend, n, |_, i| {
+                density_from_neighbors(&spec, d, knn.neighbors(i, k), kth, weights)
+            }))
+        }
+        DensitySpec::KdeGaussianFullBrute { .. } | DensitySpec::KdeRange { .. } => {
+            let radius2 = spec.range_radius2();
+            Ok(X::map(backend, n, |b, i| {
+                density_from_neighbors(&spec, d, &b.range_dist2(i, radius2), |_| 0.0, weights)
+            }))
+        }
+    }
+}
+
+/// Density of a point that is not stored in the backend, with the same estimator as
+/// `estimate_density`. The sample point estimator also queries the k nearest neighbors of each
+/// neighbor of the point, k + 1 backend queries in all.
+pub fn density_at_point<B: AnnBackend>(
+    backend: &B,
+    spec: &DensitySpec,
+    point: &[f64],
+) -> Result<f64, TomatoError> {
+    validate_spec(spec)?;
+    validate_dim(spec, backend.len(), backend.dim())?;
+    point_density(backend, spec, point, None)
+}
+
+/// Same as `density_at_point` with the weights of the stored points, as
+/// `estimate_density_weighted`.
+pub fn density_at_point_weighted<B: AnnBackend>(
+    backend: &B,
+    spec: &DensitySpec,
+    point: &[f64],
+    weights: &[f64],
+) -> Result<f64, TomatoError> {
+    validate_spec(spec)?;
+    validate_dim(spec, backend.len(), backend.dim())?;
+    validate_weights(weights, backend.len())?;
+    point_density(backend, spec, point, Some(weights))
+}
+
+fn point_density<B: AnnBackend>(
+    backend: &B,
+    spec: &DensitySpec,
+    point: &[f64],
+    weights: Option<&[f64]>,
+) -> Result<f64, TomatoError> {
+    let nbrs = match *spec {
+        DensitySpec::KnnLog { k, .. }
+        | DensitySpec::Dtm { k, .. }
+        | DensitySpec::KdeGaussianKnn { k, .. }
+        | DensitySpec::KdeKnn { k, .. }
+        | DensitySpec::KdeGaussianAdaptive { k, .. } => backend.knn_point_dist2(point, k)?,
+        DensitySpec::KdeGaussianFullBrute { .. } | DensitySpec::KdeRange { .. } => {
+            backend.range_point_dist2(point, spec.range_radius2())?
+        }
+    };
+    let k = spec.knn_k().unwrap_or(0);
+    if !has_mass(spec, &nbrs, weights) {
+        return Err(TomatoError::InvalidWeights(format!(
+            "the {} nearest neighbors of the query all weigh 0",
+            k
+        )));
+    }
+    let kth = |j: usize| kth_dist2(&backend.knn_indices_dist2(j, k));
+    Ok(density_from_neighbors(spec, backend.dim(), &nbrs, kth, weights))
+}
+
+/// `density_at_point` for each of `points`.
+pub fn estimate_density_at<B: AnnBackend>(
+    backend: &B,
+    spec: &DensitySpec,
+    points: &[Vec<f64>],
+) -> Result<Vec<f64>, TomatoError> {
+    Seq::try_map(backend, points.len(), |b, i| density_at_point(b, spec, &points[i]))
+}
+
+/// `density_at_point_weighted` for each of `points`.
+pub fn estimate_density_at_weighted<B: AnnBackend>(
+    backend: &B,
+    spec: &DensitySpec,
+    points: &[Vec<f64>],
+    weights: &[f64],
+) -> Result<Vec<f64>, TomatoError> {
+    validate_spec(spec)?;
+    validate_dim(spec, backend.len(), backend.dim())?;
+    validate_weights(weights, backend.len())?;
+    Seq::try_map(backend, points.len(), |b, i| point_density(b, spec, &points[i], Some(weights)))
+}
+
+/// Same as `estimate_density_at`, with the points spread over the rayon pool.
+#[cfg(feature = "parallel")]
+pub fn estimate_density_at_par<B: AnnBackend + Sync>(
+    backend: &B,
+    spec: &DensitySpec,
+    points: &[Vec<f64>],
+) -> Result<Vec<f64>, TomatoError> {
+    Par::try_map(backend, points.len(), |b, i| density_at_point(b, spec, &points[i]))
+}
@@ -2,9 +2,15 @@
 
 use crate::backend::AnnBackend;
 use crate::graph::Graph;
+#[cfg(feature = "parallel")]
+use crate::parallel::Par;
+use crate::parallel::{Exec, Seq};
+use crate::pipeline::knn::KnnTable;
 use crate::tomato::TomatoError;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum GraphSpec {
     Knn {
         k: usize,
@@ -20,59 +26,106 @@ pub enum GraphSpec {
     },
 }
 
+impl GraphSpec {
+    /// The number of neighbors the spec reads from a kNN table, `None` for range based specs.
+    pub fn knn_k(&self) -> Option<usize> {
+        match *self {
+            GraphSpec::Knn { k, .. } | GraphSpec::RipsFromKnnApprox { k, .. } => Some(k),
+            GraphSpec::RipsBrute { .. } => None,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TomatoError> {
+        match *self {
+            GraphSpec::Knn { .. } => Ok(()),
+            GraphSpec::RipsBrute { radius2 } | GraphSpec::RipsFromKnnApprox { radius2, .. } => {
+                if radius2.is_nan() || radius2 < 0.0 {
+                    return Err(TomatoError::InvalidGraph("radius2 must be >= 0".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 pub fn build_graph<B: AnnBackend>(backend: &B, spec: GraphSpec) -> Result<Graph, TomatoError> {
+    let knn = spec.knn_k().map(|k| KnnTable::build(backend, k));
+    build::<B, Seq>(backend, spec, knn.as_ref())
+}
+
+/// Same as `build_graph`, with the per point queries spread over the rayon pool.
+#[cfg(feature = "parallel")]
+pub fn build_graph_par<B: AnnBackend + Sync>(backend: &B, spec: GraphSpec) -> Result<Graph, TomatoError> {
+    let knn = spec.knn_k().map(|k| KnnTable::build_par(backend, k));
+    build::<B, Par>(backend, spec, knn.as_ref())
+}
+
+/// Same as `build_graph`, with kNN specs answered from `knn` instead of new backend queries.
+pub fn build_graph_with_knn<B: AnnBackend>(
+    backend: &B,
+    spec: GraphSpec,
+    knn: &KnnTable,
+) -> Result<Graph, TomatoError> {
+    build_with_knn::<B, Seq>(backend, spec, knn)
+}
+
+pub(crate) fn build_with_knn<B: AnnBackend, X: Exec<B>>(
+    backend: &B,
+    spec: GraphSpec,
+    knn: &KnnTable,
+) -> Result<Graph, TomatoError> {
+    if let Some(k) = spec.knn_k() {
+        knn.check(backend, k)?;
+    }
+    build::<B, X>(backend, spec, Some(knn))
+}
+
+// `knn` is Some whenever the spec is kNN based.
+fn build<B: AnnBackend, X: Exec<B>>(
+    backend: &B,
+    spec: GraphSpec,
+    knn: Option<&KnnTable>,
+) -> Result<Graph, TomatoError> {
+    spec.validate()?;
     let n = backend.len();
     match spec {
         GraphSpec::Knn { k, symmetrize } => {
-            let knn = backend.knn_all_indices_dist2(k);
-            let mut adj: Vec<Vec<usize>> = Vec::with_capacity(n);
-            for i in 0..n {
-                let mut nbrs: Vec<usize> = knn[i].iter().map(|x| x.0).collect();
+            let knn = knn.unwrap();
+            let mut adj: Vec<Vec<usize>> = X::map(backend, n, |_, i| {
+                let mut nbrs: Vec<usize> = knn.neighbors(i, k).iter().map(|x| x.0).collect();
                 nbrs.sort_unstable();
                 nbrs.dedup();
-                adj.push(nbrs);
-            }
+                nbrs
+            });
             if symmetrize {
                 adj = Graph::symmetrize_and_dedup(adj);
             }
             Graph::new(adj)
         }
         GraphSpec::RipsBrute { radius2 } => {
-            if !(radius2 >= 0.0) {
-                return Err(TomatoError::InvalidGraph("radius2 must be >= 0".to_string()));
-            }
-            let mut adj: Vec<Vec<usize>> = vec![Vec::new(); n];
-            for i in 0..n {
-                let mut nbrs: Vec<(usize, f64)> = backend.knn_indices_dist2(i, n.saturating_sub(1));
-                nbrs.retain(|&(_j, d2)| d2 <= radius2);
-                for (j, _) in nbrs {
-                    adj[i].push(j);
-                }
-            }
-            adj = Graph::symmetrize_and_dedup(adj);
+            let adj: Vec<Vec<usize>> = X::map(backend, n, |b, i| {
+                b.range_dist2(i, radius2).into_iter().map(|x| x.0).collect()
+            });
+            let adj = Graph::symmetrize_and_dedup(adj);
             Graph::new(adj)
         }
         GraphSpec::RipsFromKnnApprox { k, radius2, symmetrize } => {
-            if !(radius2 >= 0.0) {
-                return Err(TomatoError::InvalidGraph("radius2 must be >= 0".to_string()));
-            }
-            let knn = backend.knn_all_indices_dist2(k);
-            let mut adj: Vec<Vec<usize>> = Vec::with_capacity(n);
-            for i in 0..n {
+            let knn = knn.unwrap();
+            let mut adj: Vec<Vec<usize>> = X::map(backend, n, |_, i| {
                 let mut nbrs: Vec<usize> = Vec::new();
-                for &(j, d2) in &knn[i] {
+                for &(j, d2) in knn.neighbors(i, k) {
                     if d2 <= radius2 {
                         nbrs.push(j);
                     }
                 }
                 nbrs.sort_unstable();
                 nbrs.dedup();
-                adj.push(nbrs);
-            }
+                nbrs
+            });
             if symmetrize {
                 adj = Graph::symmetrize_and_dedup(adj);
             }
             Graph::new(adj)
         }
     }
-}
\ No newline at end of file
+}
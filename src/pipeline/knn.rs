@@ -0,0 +1,135 @@
+#![forbid(unsafe_code)]
+
+use crate::backend::AnnBackend;
+#[cfg(feature = "parallel")]
+use crate::parallel::{Exec, Par};
+use crate::tomato::TomatoError;
+
+/// The k nearest neighbors of every stored point, each row sorted by squared distance.
+///
+/// A table built for k answers every kNN spec with k' <= k through the first k' entries of each
+/// row. For exact backends this is the same as querying with k'; for HNSW it is the answer of
+/// the wider search.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "KnnTableRepr", into = "KnnTableRepr")
+)]
+pub struct KnnTable {
+    k: usize,
+    rows: Vec<Vec<(usize, f64)>>,
+}
+
+impl KnnTable {
+    pub fn build<B: AnnBackend>(backend: &B, k: usize) -> Self {
+        let rows = if k == 0 {
+            vec![Vec::new(); backend.len()]
+        } else {
+            backend.knn_all_indices_dist2(k)
+        };
+        Self { k, rows }
+    }
+
+    /// Same as `build`, with the `knn_indices_dist2` queries spread over the rayon pool.
+    #[cfg(feature = "parallel")]
+    pub fn build_par<B: AnnBackend + Sync>(backend: &B, k: usize) -> Self {
+        let rows = if k == 0 {
+            vec![Vec::new(); backend.len()]
+        } else {
+            Par::map(backend, backend.len(), |b, i| b.knn_indices_dist2(i, k))
+        };
+        Self { k, rows }
+    }
+
+    /// Wraps rows computed elsewhere, for example loaded from a cache.
+    pub fn from_rows(k: usize, rows: Vec<Vec<(usize, f64)>>) -> Result<Self, TomatoError> {
+        let n = rows.len();
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() > k {
+                return Err(TomatoError::InvalidGraph(format!("knn row {} has more than {} entries", i, k)));
+            }
+            let mut prev = 0.0;
+            for &(j, d2) in row {
+                if j >= n || j == i {
+                    return Err(TomatoError::InvalidGraph(format!("invalid neighbor {} in knn row {}", j, i)));
+                }
+                if !d2.is_finite() || d2 < prev {
+                    return Err(TomatoError::InvalidGraph(format!("knn row {} is not sorted by distance", i)));
+                }
+                prev = d2;
+            }
+        }
+        Ok(Self { k, rows })
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn n(&self) -> usize {
+        self.rows.len()
+    }
+
+    #[inline]
+    pub fn row(&self, i: usize) -> &[(usize, f64)] {
+        &self.rows[i]
+    }
+
+    /// The first `k` entries of row `i`.
+    #[inline]
+    pub fn neighbors(&self, i: usize, k: usize) -> &[(usize, f64)] {
+        let row = &self.rows[i];
+        &row[..k.min(row.len())]
+    }
+
+    pub fn into_rows(self) -> Vec<Vec<(usize, f64)>> {
+        self.rows
+    }
+
+    pub(crate) fn check<B: AnnBackend>(&self, backend: &B, k: usize) -> Result<(), TomatoError> {
+        if self.n() != backend.len() {
+            return Err(TomatoError::InvalidGraph(format!(
+                "knn table has {} rows, backend has {} points",
+                self.n(),
+                backend.len()
+            )));
+        }
+        if k > self.k {
+            return Err(TomatoError::InvalidGraph(format!(
+                "spec needs k = {} but the knn table has k = {}",
+                k, self.k
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Serialized form of a table, checked by `from_rows` when read back.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KnnTableRepr {
+    k: usize,
+    rows: Vec<Vec<(usize, f64)>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<KnnTableRepr> for KnnTable {
+    type Error = TomatoError;
+
+    fn try_from(repr: KnnTableRepr) -> Result<Self, TomatoError> {
+        KnnTable::from_rows(repr.k, repr.rows)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<KnnTable> for KnnTableRepr {
+    fn from(table: KnnTable) -> Self {
+        KnnTableRepr {
+            k: table.k,
+            rows: table.rows,
+        }
+    }
+}
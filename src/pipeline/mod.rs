@@ -1,16 +1,47 @@
 #![forbid(unsafe_code)]
 
+pub mod bandwidth;
 pub mod density;
 pub mod graph_build;
+pub mod knn;
+pub mod model;
+pub mod radius;
+pub mod smoothing;
+pub mod sweep;
 
-pub use density::{estimate_density, DensitySpec};
-pub use graph_build::{build_graph, GraphSpec};
+pub use bandwidth::{
+    knn_bandwidth2, log_spaced, loo_bandwidth2, scott_bandwidth2, silverman_bandwidth2, BandwidthChoice,
+    BandwidthDiagnostics,
+};
+pub use density::{
+    density_at_point, density_at_point_weighted, estimate_density, estimate_density_at, estimate_density_at_weighted,
+    estimate_density_weighted, estimate_density_with_knn, estimate_density_with_knn_weighted, AdaptiveKde, DensitySpec, Kernel,
+};
+#[cfg(feature = "parallel")]
+pub use density::{estimate_density_at_par, estimate_density_par};
+pub use graph_build::{build_graph, build_graph_with_knn, GraphSpec};
+#[cfg(feature = "parallel")]
+pub use graph_build::build_graph_par;
+pub use knn::KnnTable;
+pub use model::{Prediction, TomatoModel};
+pub use radius::{
+    bandwidth_radius2, component_stats, connecting_radius2, knn_quantile_radius2, rips_component_stats,
+    ComponentStats, RadiusChoice, RadiusRule,
+};
+pub use smoothing::{smooth_density, Smoothing, SmoothingMethod};
+#[cfg(feature = "parallel")]
+pub use smoothing::smooth_density_par;
+pub use sweep::{rank_runs, sweep, SweepCriterion, SweepGrid, SweepRun, SweepTau};
 
 use crate::backend::AnnBackend;
 use crate::graph::Graph;
-use crate::tomato::{tomato_cluster, TomatoError, TomatoParams, TomatoResult};
+#[cfg(feature = "parallel")]
+use crate::parallel::Par;
+use crate::parallel::{Exec, Seq};
+use crate::tomato::{cluster, validate_weights, TomatoError, TomatoParams, TomatoResult};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PipelineParams {
     pub graph: GraphSpec,
     pub density: DensitySpec,
@@ -18,18 +49,102 @@ pub struct PipelineParams {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PipelineResult {
     pub graph: Graph,
+    /// The density the clustering ran on, after smoothing if any.
     pub density: Vec<f64>,
     pub tomato: TomatoResult,
 }
 
+impl PipelineParams {
+    pub fn new(graph: GraphSpec, density: DensitySpec, tomato: TomatoParams) -> Self {
+        Self { graph, density, tomato }
+    }
+
+    /// Sets `tomato.smoothing`, applied to the estimated density over the graph before
+    /// clustering.
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.tomato.smoothing = Some(smoothing);
+        self
+    }
+
+    /// The k of the kNN table that serves both the graph and the density spec, `None` if
+    /// neither reads one.
+    pub fn knn_k(&self) -> Option<usize> {
+        match (self.graph.knn_k(), self.density.knn_k()) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    // Checks every spec, so that invalid params fail before the kNN pass.
+    pub(crate) fn validate<B: AnnBackend>(&self, backend: &B) -> Result<(), TomatoError> {
+        self.graph.validate()?;
+        self.density.validate(backend)?;
+        self.tomato.validate()
+    }
+}
+
+/// Runs graph construction, density estimation, optional smoothing and clustering, with a single kNN pass shared
+/// by the graph and the density.
 pub fn run_pipeline<B: AnnBackend>(
     backend: &B,
     params: PipelineParams,
 ) -> Result<PipelineResult, TomatoError> {
-    let graph = build_graph(backend, params.graph)?;
-    let density = estimate_density(backend, params.density)?;
-    let tomato = tomato_cluster(&graph, &density, params.tomato)?;
+    params.validate(backend)?;
+    let knn = KnnTable::build(backend, params.knn_k().unwrap_or(0));
+    run_pipeline_with_knn(backend, params, &knn)
+}
+
+/// Same as `run_pipeline` with a kNN table built beforehand, for example shared by several
+/// runs. Its k must cover both specs.
+pub fn run_pipeline_with_knn<B: AnnBackend>(
+    backend: &B,
+    params: PipelineParams,
+    knn: &KnnTable,
+) -> Result<PipelineResult, TomatoError> {
+    run::<B, Seq>(backend, params, knn, None)
+}
+
+/// Same as `run_pipeline`, with the kNN pass, Rips range queries and density sums spread over
+/// the rayon pool.
+#[cfg(feature = "parallel")]
+pub fn run_pipeline_par<B: AnnBackend + Sync>(
+    backend: &B,
+    params: PipelineParams,
+) -> Result<PipelineResult, TomatoError> {
+    params.validate(backend)?;
+    let knn = KnnTable::build_par(backend, params.knn_k().unwrap_or(0));
+    run::<B, Par>(backend, params, &knn, None)
+}
+
+/// Same as `run_pipeline` with an optional weight per point, see `estimate_density_weighted`.
+/// Weights enter the density only; the graph and the clustering treat every point alike.
+pub fn run_pipeline_with_weights<B: AnnBackend>(
+    backend: &B,
+    params: PipelineParams,
+    weights: Option<&[f64]>,
+) -> Result<PipelineResult, TomatoError> {
+    params.validate(backend)?;
+    if let Some(w) = weights {
+        validate_weights(w, backend.len())?;
+    }
+    let knn = KnnTable::build(backend, params.knn_k().unwrap_or(0));
+    run::<B, Seq>(backend, params, &knn, weights)
+}
+
+fn run<B: AnnBackend, X: Exec<B> + Exec<Graph>>(
+    backend: &B,
+    params: PipelineParams,
+    knn: &KnnTable,
+    weights: Option<&[f64]>,
+) -> Result<PipelineResult, TomatoError> {
+    let graph = graph_build::build_with_knn::<B, X>(backend, params.graph, knn)?;
+    let mut density = density::estimate_with_knn::<B, X>(backend, params.density, knn, weights)?;
+    if let Some(smoothing) = &params.tomato.smoothing {
+        density = smoothing::smooth::<X>(&graph, &density, smoothing)?;
+    }
+    let tomato = cluster(&graph, &density, &params.tomato)?;
     Ok(PipelineResult { graph, density, tomato })
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+#![forbid(unsafe_code)]
+
+use crate::backend::AnnBackend;
+use crate::graph::Graph;
+use crate::hierarchy::MergeTree;
+use crate::order::higher;
+use crate::pipeline::density::{
+    density_at_point, density_at_point_weighted, estimate_density_with_knn, estimate_density_with_knn_weighted,
+};
+use crate::pipeline::graph_build::{build_graph_with_knn, GraphSpec};
+use crate::pipeline::knn::KnnTable;
+use crate::pipeline::smoothing::smooth_density;
+use crate::pipeline::PipelineParams;
+use crate::tomato::{Merges, TomatoError, TomatoResult, NOISE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Prediction {
+    pub density: f64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::label"))]
+    pub label: usize,
+}
+
+/// A fitted pipeline that keeps its backend, density and merge hierarchy to label new points.
+pub struct TomatoModel<B: AnnBackend> {
+    backend: B,
+    params: PipelineParams,
+    weights: Option<Vec<f64>>,
+    graph: Graph,
+    density: Vec<f64>,
+    tree: MergeTree,
+    merges: Merges,
+    tomato: TomatoResult,
+}
+
+impl<B: AnnBackend> TomatoModel<B> {
+    pub fn fit(backend: B, params: PipelineParams) -> Result<Self, TomatoError> {
+        Self::fit_with_weights(backend, params, None)
+    }
+
+    /// Same as `fit` with an optional weight per point, as `run_pipeline_with_weights`. The
+    /// weights are kept for the densities of predicted points.
+    pub fn fit_with_weights(
+        backend: B,
+        params: PipelineParams,
+        weights: Option<Vec<f64>>,
+    ) -> Result<Self, TomatoError> {
+        params.validate(&backend)?;
+        let knn = KnnTable::build(&backend, params.knn_k().unwrap_or(0));
+        let graph = build_graph_with_knn(&backend, params.graph.clone(), &knn)?;
+        let mut density = match &weights {
+            Some(w) => estimate_density_with_knn_weighted(&backend, params.density.clone(), &knn, w)?,
+            None => estimate_density_with_knn(&backend, params.density.clone(), &knn)?,
+        };
+        if let Some(smoothing) = &params.tomato.smoothing {
+            density = smooth_density(&graph, &density, smoothing)?;
+        }
+        let tree = MergeTree::new(&graph, &density)?;
+        let tomato = tree.result(&params.tomato)?;
+        let merges = tree.merges_at(params.tomato.tau)?;
+        Ok(Self {
+            backend,
+            params,
+            weights,
+            graph,
+            density,
+            tree,
+            merges,
+            tomato,
+        })
+    }
+
+    #[inline]
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    #[inline]
+    pub fn params(&self) -> &PipelineParams {
+        &self.params
+    }
+
+    #[inline]
+    pub fn weights(&self) -> Option<&[f64]> {
+        self.weights.as_deref()
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    #[inline]
+    pub fn density(&self) -> &[f64] {
+        &self.density
+    }
+
+    #[inline]
+    pub fn tree(&self) -> &MergeTree {
+        &self.tree
+    }
+
+    #[inline]
+    pub fn result(&self) -> &TomatoResult {
+        &self.tomato
+    }
+
+    #[inline]
+    pub fn labels(&self) -> &[usize] {
+        &self.tomato.cluster_of
+    }
+
+    /// Labels a new point as a refit would if the point changed no mode.
+    ///
+    /// The point gets its density from the fitted estimator and its graph neighbors from the
+    /// fitted graph spec, then hangs below the highest mode among the components of its upper
+    /// neighbors, as the union-find at the fitted tau forms them. A point above all its
+    /// neighbors meets the component of its highest neighbor there, and joins it when the
+    /// lower of the two peaks rises less than tau above that neighbor. The point is labelled
+    /// `NOISE` when a refit would make it its own peak, which at tau = 0 it always is, when it
+    /// has no graph neighbors, or when it falls below the noise density floor.
+    ///
+    /// With smoothing, the density of the point is smoothed over its neighbors for the same
+    /// number of iterations, their fitted densities held fixed.
+    pub fn predict(&self, point: &[f64]) -> Result<Prediction, TomatoError> {
+        let mut density = match &self.weights {
+            Some(w) => density_at_point_weighted(&self.backend, &self.params.density, point, w)?,
+            None => density_at_point(&self.backend, &self.params.density, point)?,
+        };
+
+        let neighbors: Vec<usize> = match self.params.graph {
+            GraphSpec::Knn { k, .. } => self
+                .backend
+                .knn_point_dist2(point, k)?
+                .into_iter()
+                .map(|x| x.0)
+                .collect(),
+            GraphSpec::RipsBrute { radius2 } => self
+                .backend
+                .range_point_dist2(point, radius2)?
+                .into_iter()
+                .map(|x| x.0)
+                .collect(),
+            GraphSpec::RipsFromKnnApprox { k, radius2, .. } => self
+                .backend
+                .knn_point_dist2(point, k)?
+                .into_iter()
+                .filter(|&(_j, d2)| d2 <= radius2)
+                .map(|x| x.0)
+                .collect(),
+        };
+
+        if let Some(smoothing) = &self.params.tomato.smoothing {
+            for _ in 0..smoothing.iterations {
+                density = smoothing.combine(density, neighbors.iter().map(|&u| self.density[u]));
+            }
+        }
+
+        let noise = &self.params.tomato.noise;
+        let below_floor = noise.min_density.is_some_and(|x| density < x);
+
+        let mut top = self.merges.attach(&self.density, &neighbors, density);
+        let mut lifetime = 0.0;
+        if top.is_none() {
+            let mut highest: Option<usize> = None;
+            for &u in &neighbors {
+                if highest.is_none_or(|h| higher(&self.density, u, h)) {
+                    highest = Some(u);
+                }
+            }
+            if let Some(u) = highest {
+                top = self.merges.attach(&self.density, &[u], self.density[u]);
+                if let Some(w) = top {
+                    lifetime = density.min(self.density[w]) - self.density[u];
+                }
+            }
+        }
+
+        let label = match top {
+            Some(w) if lifetime < self.params.tomato.tau && !below_floor => self.tomato.cluster_of[w],
+            _ => NOISE,
+        };
+
+        Ok(Prediction { density, label })
+    }
+}
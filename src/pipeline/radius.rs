@@ -0,0 +1,168 @@
+#![forbid(unsafe_code)]
+
+use crate::backend::AnnBackend;
+use crate::graph::Graph;
+use crate::pipeline::bandwidth::quantile;
+use crate::pipeline::graph_build::{build_graph, GraphSpec};
+use crate::pipeline::knn::KnnTable;
+use crate::tomato::TomatoError;
+use crate::uf::UfTomato;
+
+/// Connected components of a graph.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComponentStats {
+    pub n_components: usize,
+    /// Size of the largest component, and that size over the number of vertices.
+    pub largest: usize,
+    pub largest_fraction: f64,
+    /// Components of a single vertex.
+    pub n_singletons: usize,
+    pub mean_degree: f64,
+}
+
+/// The rule behind a suggested radius and its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RadiusRule {
+    KnnQuantile { k: usize, q: f64 },
+    Connecting { fraction: f64, k: usize },
+    Bandwidth { bandwidth2: f64, scale: f64 },
+}
+
+/// A suggested `radius2` with the components of the Rips graph it builds, that is the graph of
+/// `GraphSpec::RipsBrute` at that radius.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RadiusChoice {
+    pub radius2: f64,
+    pub rule: RadiusRule,
+    pub stats: ComponentStats,
+}
+
+pub fn component_stats(graph: &Graph) -> ComponentStats {
+    let n = graph.n();
+    let mut uf = UfTomato::new(n);
+    let mut degree = 0usize;
+    for u in 0..n {
+        degree += graph.neighbors(u).len();
+        for &v in graph.neighbors(u) {
+            uf.union(u, v);
+        }
+    }
+    let mut n_components = 0;
+    let mut largest = 0;
+    let mut n_singletons = 0;
+    for v in 0..n {
+        if uf.find(v) == v {
+            n_components += 1;
+            largest = largest.max(uf.size_of_root(v));
+            if uf.size_of_root(v) == 1 {
+                n_singletons += 1;
+            }
+        }
+    }
+    ComponentStats {
+        n_components,
+        largest,
+        largest_fraction: if n == 0 { 0.0 } else { largest as f64 / n as f64 },
+        n_singletons,
+        mean_degree: if n == 0 { 0.0 } else { degree as f64 / n as f64 },
+    }
+}
+
+/// Components of the Rips graph of the backend points at `radius2`.
+pub fn rips_component_stats<B: AnnBackend>(backend: &B, radius2: f64) -> Result<ComponentStats, TomatoError> {
+    Ok(component_stats(&build_graph(backend, GraphSpec::RipsBrute { radius2 })?))
+}
+
+fn radius_error(msg: String) -> TomatoError {
+    TomatoError::Radius(msg)
+}
+
+fn choice<B: AnnBackend>(backend: &B, radius2: f64, rule: RadiusRule) -> Result<RadiusChoice, TomatoError> {
+    Ok(RadiusChoice {
+        radius2,
+        rule,
+        stats: rips_component_stats(backend, radius2)?,
+    })
+}
+
+/// The q quantile over all points of the squared distance to the k-th nearest neighbor. A
+/// fraction q of the points then has at least k neighbors within the radius; q = 1 leaves no
+/// point with fewer.
+pub fn knn_quantile_radius2<B: AnnBackend>(backend: &B, k: usize, q: f64) -> Result<RadiusChoice, TomatoError> {
+    if k == 0 {
+        return Err(radius_error("k must be >= 1".to_string()));
+    }
+    if !(0.0..=1.0).contains(&q) {
+        return Err(radius_error("quantile must be in [0, 1]".to_string()));
+    }
+    let knn = KnnTable::build(backend, k);
+    let mut kth: Vec<f64> = (0..knn.n()).filter_map(|i| knn.row(i).last().map(|x| x.1)).collect();
+    if kth.is_empty() {
+        return Err(radius_error("need at least 2 points".to_string()));
+    }
+    kth.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    choice(backend, quantile(&kth, q), RadiusRule::KnnQuantile { k, q })
+}
+
+/// The smallest radius whose Rips graph has a component holding at least `fraction` of the
+/// points, the single linkage height at which that component forms.
+///
+/// Only the edges to the k nearest neighbors of each point are scanned, so the result is an
+/// upper bound, exact whenever those edges contain the minimum spanning tree, always for
+/// k = n - 1. A fraction the kNN graph cannot reach is an error that asks for a larger k.
+pub fn connecting_radius2<B: AnnBackend>(backend: &B, fraction: f64, k: usize) -> Result<RadiusChoice, TomatoError> {
+    let n = backend.len();
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(radius_error("fraction must be in (0, 1]".to_string()));
+    }
+    if k == 0 {
+        return Err(radius_error("k must be >= 1".to_string()));
+    }
+    let rule = RadiusRule::Connecting { fraction, k };
+    let target = (fraction * n as f64).ceil() as usize;
+    if target <= 1 {
+        return choice(backend, 0.0, rule);
+    }
+
+    let knn = KnnTable::build(backend, k);
+    let mut edges: Vec<(f64, usize, usize)> = Vec::new();
+    for i in 0..n {
+        for &(j, d2) in knn.row(i) {
+            edges.push((d2, i, j));
+        }
+    }
+    edges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let mut uf = UfTomato::new(n);
+    for (d2, i, j) in edges {
+        let root = uf.union(i, j);
+        if uf.size_of_root(root) >= target {
+            return choice(backend, d2, rule);
+        }
+    }
+    Err(radius_error(format!(
+        "the {} nearest neighbor graph connects fewer than {} of {} points, raise k",
+        k, target, n
+    )))
+}
+
+/// The radius scale times the kernel sigma, so radius2 = scale² · bandwidth2. Every edge of the
+/// Rips graph then joins points whose Gaussian kernel weight is at least exp(-scale² / 2), for
+/// example 0.135 at scale 2.
+pub fn bandwidth_radius2<B: AnnBackend>(
+    backend: &B,
+    bandwidth2: f64,
+    scale: f64,
+) -> Result<RadiusChoice, TomatoError> {
+    if !bandwidth2.is_finite() || bandwidth2 <= 0.0 {
+        return Err(radius_error("bandwidth2 must be finite and > 0".to_string()));
+    }
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(radius_error("scale must be finite and > 0".to_string()));
+    }
+    choice(backend, scale * scale * bandwidth2, RadiusRule::Bandwidth { bandwidth2, scale })
+}
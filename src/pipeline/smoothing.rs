@@ -0,0 +1,110 @@
+#![forbid(unsafe_code)]
+
+use crate::graph::Graph;
+#[cfg(feature = "parallel")]
+use crate::parallel::Par;
+use crate::parallel::{Exec, Seq};
+use crate::tomato::TomatoError;
+
+/// How a vertex combines its own density with those of its graph neighbors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SmoothingMethod {
+    Mean,
+    /// The median of the vertex and its neighbors, the mean of the two middle values for an even
+    /// count. It removes isolated peaks without lowering the plateaus around them.
+    Median,
+    /// The vertex weighs `self_weight` and each neighbor 1, so 1 is `Mean` and larger weights
+    /// smooth less.
+    WeightedMean { self_weight: f64 },
+}
+
+/// Graph smoothing of the density between estimation and clustering, as suggested by the
+/// ToMATo paper to flatten small spurious peaks. Each iteration replaces every value with
+/// `method` over the vertex and its `Graph::neighbors`, all from the previous iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Smoothing {
+    pub method: SmoothingMethod,
+    pub iterations: usize,
+}
+
+impl Smoothing {
+    pub fn new(method: SmoothingMethod, iterations: usize) -> Self {
+        Self { method, iterations }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TomatoError> {
+        if let SmoothingMethod::WeightedMean { self_weight } = self.method {
+            if !self_weight.is_finite() || self_weight < 0.0 {
+                return Err(TomatoError::InvalidSmoothing(
+                    "self_weight must be finite and >= 0".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // One step for a vertex of density `own` whose neighbors have the densities `nbrs`.
+    pub(crate) fn combine(&self, own: f64, nbrs: impl Iterator<Item = f64>) -> f64 {
+        match self.method {
+            SmoothingMethod::Mean => weighted_mean(own, 1.0, nbrs),
+            SmoothingMethod::WeightedMean { self_weight } => weighted_mean(own, self_weight, nbrs),
+            SmoothingMethod::Median => {
+                let mut xs: Vec<f64> = std::iter::once(own).chain(nbrs).collect();
+                xs.sort_by(|a, b| a.total_cmp(b));
+                let m = xs.len() / 2;
+                if xs.len() % 2 == 1 {
+                    xs[m]
+                } else {
+                    0.5 * (xs[m - 1] + xs[m])
+                }
+            }
+        }
+    }
+}
+
+fn weighted_mean(own: f64, self_weight: f64, nbrs: impl Iterator<Item = f64>) -> f64 {
+    let mut sum = self_weight * own;
+    let mut weight = self_weight;
+    for x in nbrs {
+        sum += x;
+        weight += 1.0;
+    }
+    // a vertex without neighbors and with weight 0 keeps its value
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        own
+    }
+}
+
+/// The density after `smoothing.iterations` rounds of smoothing over `graph`.
+pub fn smooth_density(graph: &Graph, density: &[f64], smoothing: &Smoothing) -> Result<Vec<f64>, TomatoError> {
+    smooth::<Seq>(graph, density, smoothing)
+}
+
+/// Same as `smooth_density`, with the vertices of each round spread over the rayon pool.
+#[cfg(feature = "parallel")]
+pub fn smooth_density_par(graph: &Graph, density: &[f64], smoothing: &Smoothing) -> Result<Vec<f64>, TomatoError> {
+    smooth::<Par>(graph, density, smoothing)
+}
+
+pub(crate) fn smooth<X: Exec<Graph>>(
+    graph: &Graph,
+    density: &[f64],
+    smoothing: &Smoothing,
+) -> Result<Vec<f64>, TomatoError> {
+    smoothing.validate()?;
+    if density.len() != graph.n() {
+        return Err(TomatoError::DensityLengthMismatch);
+    }
+    let mut f = density.to_vec();
+    for _ in 0..smoothing.iterations {
+        f = X::map(graph, graph.n(), |g, v| {
+            smoothing.combine(f[v], g.neighbors(v).iter().map(|&u| f[u]))
+        });
+    }
+    Ok(f)
+}
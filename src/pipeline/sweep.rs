@@ -0,0 +1,371 @@
+#![forbid(unsafe_code)]
+
+use std::cmp::Ordering;
+
+use crate::backend::AnnBackend;
+use crate::hierarchy::MergeTree;
+use crate::metrics::{adjusted_rand_index, normalized_mutual_info, silhouette, NoiseHandling};
+use crate::persistence::PersistencePair;
+use crate::pipeline::density::{estimate_density_with_knn, DensitySpec};
+use crate::pipeline::graph_build::{build_graph_with_knn, GraphSpec};
+use crate::pipeline::knn::KnnTable;
+use crate::pipeline::smoothing::{smooth_density, Smoothing};
+use crate::selection::{select_tau, TauCriterion};
+use crate::tomato::{NoisePolicy, TomatoError, TomatoParams};
+
+/// A tau given directly or read off each diagram of the sweep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SweepTau {
+    Value(#[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))] f64),
+    Select(TauCriterion),
+}
+
+/// The parameter ranges of a sweep.
+///
+/// `graph` and `density` are templates: each non empty range replaces the matching field of
+/// the template, and the sweep runs every combination. A range for a field the template does
+/// not have is an error. `smoothing` applies to every run. `truth` and `silhouette` add quality
+/// metrics to each run.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SweepGrid {
+    pub graph: GraphSpec,
+    pub density: DensitySpec,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub graph_k: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub radius2: Vec<f64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub density_k: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bandwidth2: Vec<f64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub smoothing: Option<Smoothing>,
+    pub tau: Vec<SweepTau>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub noise: NoisePolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub truth: Option<(Vec<usize>, NoiseHandling)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub silhouette: bool,
+}
+
+impl SweepGrid {
+    /// A grid of the two templates alone, with tau at the largest prominence gap.
+    pub fn new(graph: GraphSpec, density: DensitySpec) -> Self {
+        Self {
+            graph,
+            density,
+            graph_k: Vec::new(),
+            radius2: Vec::new(),
+            density_k: Vec::new(),
+            bandwidth2: Vec::new(),
+            smoothing: None,
+            tau: vec![SweepTau::Select(TauCriterion::LargestGap)],
+            noise: NoisePolicy::default(),
+            truth: None,
+            silhouette: false,
+        }
+    }
+
+    pub fn with_graph_k(mut self, k: Vec<usize>) -> Self {
+        self.graph_k = k;
+        self
+    }
+
+    pub fn with_radius2(mut self, radius2: Vec<f64>) -> Self {
+        self.radius2 = radius2;
+        self
+    }
+
+    pub fn with_density_k(mut self, k: Vec<usize>) -> Self {
+        self.density_k = k;
+        self
+    }
+
+    pub fn with_bandwidth2(mut self, bandwidth2: Vec<f64>) -> Self {
+        self.bandwidth2 = bandwidth2;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = Some(smoothing);
+        self
+    }
+
+    pub fn with_tau(mut self, tau: Vec<SweepTau>) -> Self {
+        self.tau = tau;
+        self
+    }
+
+    pub fn with_noise(mut self, noise: NoisePolicy) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    /// Scores every run against `truth` with the adjusted Rand index and normalized mutual
+    /// information.
+    pub fn with_truth(mut self, truth: Vec<usize>, noise: NoiseHandling) -> Self {
+        self.truth = Some((truth, noise));
+        self
+    }
+
+    /// Scores every run with the silhouette, which costs O(n²) distance evaluations per run.
+    pub fn with_silhouette(mut self) -> Self {
+        self.silhouette = true;
+        self
+    }
+
+    /// The graph specs of the sweep, k outer and radius2 inner.
+    pub fn graph_specs(&self) -> Result<Vec<GraphSpec>, TomatoError> {
+        let mut specs = vec![self.graph.clone()];
+        if !self.graph_k.is_empty() {
+            specs = expand(&specs, &self.graph_k, |spec, x| match spec {
+                GraphSpec::Knn { symmetrize, .. } => Some(GraphSpec::Knn { k: x, symmetrize }),
+                GraphSpec::RipsFromKnnApprox { radius2, symmetrize, .. } => {
+                    Some(GraphSpec::RipsFromKnnApprox { k: x, radius2, symmetrize })
+                }
+                GraphSpec::RipsBrute { .. } => None,
+            })
+            .ok_or_else(|| missing("graph", "k"))?;
+        }
+        if !self.radius2.is_empty() {
+            specs = expand(&specs, &self.radius2, |spec, x| match spec {
+                GraphSpec::RipsBrute { .. } => Some(GraphSpec::RipsBrute { radius2: x }),
+                GraphSpec::RipsFromKnnApprox { k, symmetrize, .. } => {
+                    Some(GraphSpec::RipsFromKnnApprox { k, radius2: x, symmetrize })
+                }
+                GraphSpec::Knn { .. } => None,
+            })
+            .ok_or_else(|| missing("graph", "radius2"))?;
+        }
+        Ok(specs)
+    }
+
+    /// The density specs of the sweep, k outer and bandwidth2 inner.
+    pub fn density_specs(&self) -> Result<Vec<DensitySpec>, TomatoError> {
+        let mut specs = vec![self.density.clone()];
+        if !self.density_k.is_empty() {
+            specs = expand(&specs, &self.density_k, |spec, x| match spec {
+                DensitySpec::KnnLog { eps, .. } => Some(DensitySpec::KnnLog { k: x, eps }),
+                DensitySpec::Dtm { p, eps, .. } => Some(DensitySpec::Dtm { k: x, p, eps }),
+                DensitySpec::KdeGaussianKnn { bandwidth2, .. } => {
+                    Some(DensitySpec::KdeGaussianKnn { k: x, bandwidth2 })
+                }
+                DensitySpec::KdeKnn { kernel, bandwidth2, .. } => Some(DensitySpec::KdeKnn { kernel, k: x, bandwidth2 }),
+                DensitySpec::KdeGaussianAdaptive { bandwidth2, method, .. } => {
+                    Some(DensitySpec::KdeGaussianAdaptive { k: x, bandwidth2, method })
+                }
+                DensitySpec::KdeGaussianFullBrute { .. } | DensitySpec::KdeRange { .. } => None,
+            })
+            .ok_or_else(|| missing("density", "k"))?;
+        }
+        if !self.bandwidth2.is_empty() {
+            specs = expand(&specs, &self.bandwidth2, |spec, x| match spec {
+                DensitySpec::KdeGaussianKnn { k, .. } => Some(DensitySpec::KdeGaussianKnn { k, bandwidth2: x }),
+                DensitySpec::KdeGaussianFullBrute { .. } => Some(DensitySpec::KdeGaussianFullBrute { bandwidth2: x }),
+                DensitySpec::KdeKnn { kernel, k, .. } => Some(DensitySpec::KdeKnn { kernel, k, bandwidth2: x }),
+                DensitySpec::KdeRange { kernel, .. } => Some(DensitySpec::KdeRange { kernel, bandwidth2: x }),
+                DensitySpec::KdeGaussianAdaptive { k, method, .. } => {
+                    Some(DensitySpec::KdeGaussianAdaptive { k, bandwidth2: x, method })
+                }
+                DensitySpec::KnnLog { .. } | DensitySpec::Dtm { .. } => None,
+            })
+            .ok_or_else(|| missing("density", "bandwidth2"))?;
+        }
+        Ok(specs)
+    }
+}
+
+fn expand<S: Clone, X: Copy>(specs: &[S], values: &[X], set: impl Fn(S, X) -> Option<S>) -> Option<Vec<S>> {
+    let mut out = Vec::with_capacity(specs.len() * values.len());
+    for spec in specs {
+        for &x in values {
+            out.push(set(spec.clone(), x)?);
+        }
+    }
+    Some(out)
+}
+
+fn missing(what: &str, field: &str) -> TomatoError {
+    TomatoError::InvalidGraph(format!("{} spec has no {} to sweep", what, field))
+}
+
+/// One combination of a sweep and its outcome.
+///
+/// `gap` is the persistence gap around tau: the smallest prominence kept minus the largest
+/// prominence merged, infinite when only essential classes survive. The metrics are `None`
+/// when not asked for, and the silhouette also when the cut leaves fewer than two clusters.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SweepRun {
+    pub graph: GraphSpec,
+    pub density: DensitySpec,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))]
+    pub tau: f64,
+    pub n_clusters: usize,
+    pub n_noise: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))]
+    pub gap: f64,
+    pub ari: Option<f64>,
+    pub nmi: Option<f64>,
+    pub silhouette: Option<f64>,
+}
+
+/// What `rank_runs` puts first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SweepCriterion {
+    /// Widest persistence gap.
+    Gap,
+    /// Highest adjusted Rand index against the truth.
+    Ari,
+    /// Highest normalized mutual information against the truth.
+    Nmi,
+    /// Highest silhouette.
+    Silhouette,
+    /// Cluster count closest to the target, ties broken by the widest gap.
+    NClusters(usize),
+}
+
+// Largest finite prominence below tau and smallest prominence at or above it.
+fn gap_at(diagram: &[PersistencePair], tau: f64) -> f64 {
+    let mut merged = 0.0f64;
+    let mut kept = f64::INFINITY;
+    for p in diagram {
+        let x = p.prominence();
+        if x < tau {
+            merged = merged.max(x);
+        } else {
+            kept = kept.min(x);
+        }
+    }
+    kept - merged
+}
+
+/// Runs every combination of `grid` on one backend.
+///
+/// Shared work is done once: a single kNN table with the largest k of the grid serves every
+/// kNN spec, each graph and each density is computed once, and each graph and density pair
+/// builds one merge hierarchy that every tau cuts. Runs come out graph outer, then density,
+/// then tau. A tau criterion with no solution on a diagram, such as `NClusters` asking for
+/// more clusters than there are modes, yields no run for that combination.
+pub fn sweep<B: AnnBackend>(backend: &B, grid: &SweepGrid) -> Result<Vec<SweepRun>, TomatoError> {
+    if let Some((truth, _)) = &grid.truth {
+        if truth.len() != backend.len() {
+            return Err(TomatoError::InvalidLabels(format!(
+                "{} truth labels for {} points",
+                truth.len(),
+                backend.len()
+            )));
+        }
+    }
+    for tau in &grid.tau {
+        if let SweepTau::Value(t) = *tau {
+            if t.is_nan() || t < 0.0 {
+                return Err(TomatoError::InvalidTau);
+            }
+        }
+    }
+    grid.noise.validate()?;
+    if let Some(smoothing) = &grid.smoothing {
+        smoothing.validate()?;
+    }
+
+    let graphs = grid.graph_specs()?;
+    let densities = grid.density_specs()?;
+    let k = graphs
+        .iter()
+        .filter_map(|s| s.knn_k())
+        .chain(densities.iter().filter_map(|s| s.knn_k()))
+        .max()
+        .unwrap_or(0);
+    let knn = KnnTable::build(backend, k);
+
+    let mut density_values = Vec::with_capacity(densities.len());
+    for spec in &densities {
+        density_values.push(estimate_density_with_knn(backend, spec.clone(), &knn)?);
+    }
+
+    let mut runs = Vec::new();
+    for gspec in &graphs {
+        let graph = build_graph_with_knn(backend, gspec.clone(), &knn)?;
+        for (dspec, density) in densities.iter().zip(&density_values) {
+            let tree = match &grid.smoothing {
+                Some(smoothing) => MergeTree::new(&graph, &smooth_density(&graph, density, smoothing)?)?,
+                None => MergeTree::new(&graph, density)?,
+            };
+            let diagram = tree.diagram();
+            for choice in &grid.tau {
+                let tau = match *choice {
+                    SweepTau::Value(t) => t,
+                    SweepTau::Select(criterion) => match select_tau(&diagram, criterion) {
+                        Ok(sel) => sel.tau,
+                        Err(TomatoError::TauSelection(_)) => continue,
+                        Err(e) => return Err(e),
+                    },
+                };
+                let res = tree.result(&TomatoParams::new(tau).with_noise(grid.noise.clone()))?;
+
+                let (ari, nmi) = match &grid.truth {
+                    Some((truth, noise)) => (
+                        Some(adjusted_rand_index(truth, &res.cluster_of, *noise)?),
+                        Some(normalized_mutual_info(truth, &res.cluster_of, *noise)?),
+                    ),
+                    None => (None, None),
+                };
+                let silhouette = if grid.silhouette {
+                    match silhouette(backend, &res.cluster_of) {
+                        Ok(s) => Some(s),
+                        Err(TomatoError::InvalidLabels(_)) => None,
+                        Err(e) => return Err(e),
+                    }
+                } else {
+                    None
+                };
+
+                runs.push(SweepRun {
+                    graph: gspec.clone(),
+                    density: dspec.clone(),
+                    tau,
+                    n_clusters: res.modes.len(),
+                    n_noise: res.n_noise(),
+                    gap: gap_at(&diagram, tau),
+                    ari,
+                    nmi,
+                    silhouette,
+                });
+            }
+        }
+    }
+    Ok(runs)
+}
+
+// Higher first, missing values last.
+fn by_score(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => y.partial_cmp(&x).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Sorts runs best first under `criterion`. The sort is stable, so equally good runs keep
+/// their sweep order; runs without the metric go last.
+pub fn rank_runs(runs: &mut [SweepRun], criterion: SweepCriterion) {
+    runs.sort_by(|a, b| match criterion {
+        SweepCriterion::Gap => by_score(Some(a.gap), Some(b.gap)),
+        SweepCriterion::Ari => by_score(a.ari, b.ari),
+        SweepCriterion::Nmi => by_score(a.nmi, b.nmi),
+        SweepCriterion::Silhouette => by_score(a.silhouette, b.silhouette),
+        SweepCriterion::NClusters(c) => a
+            .n_clusters
+            .abs_diff(c)
+            .cmp(&b.n_clusters.abs_diff(c))
+            .then_with(|| by_score(Some(a.gap), Some(b.gap))),
+    });
+}
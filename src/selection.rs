@@ -0,0 +1,150 @@
+#![forbid(unsafe_code)]
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::persistence::PersistencePair;
+use crate::tomato::TomatoError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TauCriterion {
+    /// Cut inside the widest gap between consecutive sorted finite prominences. Essential
+    /// classes count with the density span of the diagram as prominence, so that a single
+    /// dominant mode can win and keep only the essential classes.
+    LargestGap,
+    /// Cut so that exactly this many clusters survive, essential classes included.
+    NClusters(usize),
+    /// Keep modes whose prominence is at least this fraction of the diagram density span.
+    ProminenceFraction(f64),
+}
+
+/// A tau together with the part of the diagram that justifies it.
+///
+/// `merged` is the largest finite prominence below tau, zero if there is none. `kept` is the
+/// smallest prominence at or above tau, infinite if only essential classes survive.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TauSelection {
+    pub criterion: TauCriterion,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))]
+    pub tau: f64,
+    pub n_clusters: usize,
+    pub merged: f64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))]
+    pub kept: f64,
+}
+
+impl fmt::Display for TauSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tau {} keeps {} clusters, prominence gap ({}, {}) under {:?}",
+            self.tau, self.n_clusters, self.merged, self.kept, self.criterion
+        )
+    }
+}
+
+// Highest birth minus lowest death or birth, the largest prominence a finite pair can have.
+fn density_span(diagram: &[PersistencePair]) -> f64 {
+    let mut hi = f64::NEG_INFINITY;
+    let mut lo = f64::INFINITY;
+    for p in diagram {
+        hi = hi.max(p.birth);
+        lo = lo.min(p.death.unwrap_or(p.birth));
+    }
+    hi - lo
+}
+
+fn midpoint(lo: f64, hi: f64) -> f64 {
+    if hi.is_finite() {
+        0.5 * (lo + hi)
+    } else {
+        f64::INFINITY
+    }
+}
+
+pub fn select_tau(
+    diagram: &[PersistencePair],
+    criterion: TauCriterion,
+) -> Result<TauSelection, TomatoError> {
+    if diagram.is_empty() {
+        return Err(TomatoError::TauSelection("empty diagram".to_string()));
+    }
+
+    let n_essential = diagram.iter().filter(|p| p.is_essential()).count();
+
+    let mut prom: Vec<f64> = diagram
+        .iter()
+        .filter(|p| !p.is_essential())
+        .map(|p| p.prominence())
+        .collect();
+    prom.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+    // prom[i - 1] is the smallest kept prominence when i finite modes survive
+    let kept_at = |i: usize| if i == 0 { f64::INFINITY } else { prom[i - 1] };
+    let merged_at = |i: usize| if i < prom.len() { prom[i] } else { 0.0 };
+
+    let kept_modes = match criterion {
+        TauCriterion::LargestGap => {
+            let span = density_span(diagram);
+            let mut best: Option<(usize, f64)> = None;
+            for i in 0..=prom.len() {
+                let gap = if i == 0 { span } else { kept_at(i) } - merged_at(i);
+                if gap > 0.0 && best.is_none_or(|b| gap > b.1) {
+                    best = Some((i, gap));
+                }
+            }
+            match best {
+                Some((i, _)) => i,
+                None => 0,
+            }
+        }
+        TauCriterion::NClusters(k) => {
+            if k < n_essential {
+                return Err(TomatoError::TauSelection(format!(
+                    "{} clusters requested but the graph has {} components",
+                    k, n_essential
+                )));
+            }
+            let i = k - n_essential;
+            if i > prom.len() || kept_at(i).partial_cmp(&merged_at(i)) != Some(Ordering::Greater) {
+                return Err(TomatoError::TauSelection(format!(
+                    "no tau yields exactly {} clusters",
+                    k
+                )));
+            }
+            i
+        }
+        TauCriterion::ProminenceFraction(x) => {
+            if !(x > 0.0 && x <= 1.0) {
+                return Err(TomatoError::TauSelection(
+                    "prominence fraction must be in (0, 1]".to_string(),
+                ));
+            }
+            let tau = x * density_span(diagram);
+            if tau > 0.0 {
+                let i = prom.partition_point(|&p| p >= tau);
+                return Ok(TauSelection {
+                    criterion,
+                    tau,
+                    n_clusters: n_essential + i,
+                    merged: merged_at(i),
+                    kept: kept_at(i),
+                });
+            }
+            prom.partition_point(|&p| p > 0.0)
+        }
+    };
+
+    let merged = merged_at(kept_modes);
+    let kept = kept_at(kept_modes);
+    Ok(TauSelection {
+        criterion,
+        tau: midpoint(merged, kept),
+        n_clusters: n_essential + kept_modes,
+        merged,
+        kept,
+    })
+}
@@ -1,5 +1,7 @@
 #![forbid(unsafe_code)]
 
+use crate::tomato::{validate_weights, TomatoError};
+
 pub fn zscore_in_place(points: &mut [Vec<f64>]) {
     if points.is_empty() {
         return;
@@ -13,8 +15,8 @@ pub fn zscore_in_place(points: &mut [Vec<f64>]) {
             mean[j] += p[j];
         }
     }
-    for j in 0..d {
-        mean[j] /= n as f64;
+    for m in mean.iter_mut() {
+        *m /= n as f64;
     }
 
     let mut var = vec![0.0; d];
@@ -24,8 +26,54 @@ pub fn zscore_in_place(points: &mut [Vec<f64>]) {
             var[j] += t * t;
         }
     }
-    for j in 0..d {
-        var[j] /= (n as f64).max(1.0);
+    for v in var.iter_mut() {
+        *v /= (n as f64).max(1.0);
+    }
+
+    for p in points.iter_mut() {
+        for j in 0..d {
+            let sd = var[j].sqrt();
+            if sd > 0.0 {
+                p[j] = (p[j] - mean[j]) / sd;
+            } else {
+                p[j] = 0.0;
+            }
+        }
+    }
+}
+
+/// Same as `zscore_in_place` with weighted means and variances, so that a point of weight w
+/// counts as w copies of it.
+pub fn weighted_zscore_in_place(points: &mut [Vec<f64>], weights: &[f64]) -> Result<(), TomatoError> {
+    validate_weights(weights, points.len())?;
+    if points.is_empty() {
+        return Ok(());
+    }
+    let total: f64 = weights.iter().sum();
+    if total.is_nan() || total <= 0.0 {
+        return Err(TomatoError::InvalidWeights("weights sum to zero".to_string()));
+    }
+    let d = points[0].len();
+
+    let mut mean = vec![0.0; d];
+    for (p, &w) in points.iter().zip(weights) {
+        for j in 0..d {
+            mean[j] += w * p[j];
+        }
+    }
+    for m in mean.iter_mut() {
+        *m /= total;
+    }
+
+    let mut var = vec![0.0; d];
+    for (p, &w) in points.iter().zip(weights) {
+        for j in 0..d {
+            let t = p[j] - mean[j];
+            var[j] += w * t * t;
+        }
+    }
+    for v in var.iter_mut() {
+        *v /= total;
     }
 
     for p in points.iter_mut() {
@@ -38,4 +86,5 @@ pub fn zscore_in_place(points: &mut [Vec<f64>]) {
             }
         }
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -1,7 +1,10 @@
 #![forbid(unsafe_code)]
 
 use crate::graph::Graph;
+use crate::hierarchy::attach_below;
 use crate::order::{higher, vertices_desc_by_density};
+use crate::persistence::{diagram_in_order, PersistencePair};
+use crate::pipeline::smoothing::{smooth_density, Smoothing};
 use crate::uf::UfTomato;
 use thiserror::Error;
 
@@ -15,9 +18,29 @@ pub enum TomatoError {
     DensityLengthMismatch,
     #[error("invalid graph: {0}")]
     InvalidGraph(String),
+    #[error("tau selection failed: {0}")]
+    TauSelection(String),
+    #[error("invalid noise policy: {0}")]
+    InvalidNoisePolicy(String),
+    #[error("query has dimension {got}, backend has dimension {expected}")]
+    QueryDimensionMismatch { expected: usize, got: usize },
+    #[error("query has non finite value at index {0}")]
+    NonFiniteQuery(usize),
+    #[error("unsupported query: {0}")]
+    UnsupportedQuery(String),
+    #[error("invalid labels: {0}")]
+    InvalidLabels(String),
+    #[error("bandwidth selection failed: {0}")]
+    Bandwidth(String),
+    #[error("radius selection failed: {0}")]
+    Radius(String),
+    #[error("invalid smoothing: {0}")]
+    InvalidSmoothing(String),
+    #[error("invalid weights: {0}")]
+    InvalidWeights(String),
 }
 
-fn validate_density(density: &[f64]) -> Result<(), TomatoError> {
+pub(crate) fn validate_density(density: &[f64]) -> Result<(), TomatoError> {
     for (i, &x) in density.iter().enumerate() {
         if !x.is_finite() {
             return Err(TomatoError::NonFiniteDensity(i));
@@ -26,38 +49,222 @@ fn validate_density(density: &[f64]) -> Result<(), TomatoError> {
     Ok(())
 }
 
+// One finite, non negative weight per point.
+pub(crate) fn validate_weights(weights: &[f64], n: usize) -> Result<(), TomatoError> {
+    if weights.len() != n {
+        return Err(TomatoError::InvalidWeights(format!(
+            "{} weights for {} points",
+            weights.len(),
+            n
+        )));
+    }
+    if let Some(i) = weights.iter().position(|w| !w.is_finite() || *w < 0.0) {
+        return Err(TomatoError::InvalidWeights(format!(
+            "weight {} is {}, weights must be finite and >= 0",
+            i, weights[i]
+        )));
+    }
+    Ok(())
+}
+
+/// Label of points that belong to no cluster.
+pub const NOISE: usize = usize::MAX;
+
+/// Points that fail any of the set criteria get the `NOISE` label instead of a mode.
+///
+/// The criteria are applied after the tau cut: first clusters whose mode prominence is below
+/// `min_prominence`, then points whose density is below `min_density`, then clusters with fewer
+/// than `min_cluster_size` remaining points.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NoisePolicy {
+    pub min_density: Option<f64>,
+    pub min_cluster_size: Option<usize>,
+    pub min_prominence: Option<f64>,
+}
+
+impl NoisePolicy {
+    pub fn is_none(&self) -> bool {
+        self.min_density.is_none() && self.min_cluster_size.is_none() && self.min_prominence.is_none()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TomatoError> {
+        if let Some(x) = self.min_density {
+            if !x.is_finite() {
+                return Err(TomatoError::InvalidNoisePolicy("min_density must be finite".to_string()));
+            }
+        }
+        if let Some(x) = self.min_prominence {
+            if x.is_nan() || x < 0.0 {
+                return Err(TomatoError::InvalidNoisePolicy("min_prominence must be >= 0".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Built with `new` and the `with_` builders, so that options can be added without breaking
+/// callers.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct TomatoParams {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::float"))]
     pub tau: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub noise: NoisePolicy,
+    /// Applied to the density over the graph before clustering, `None` to skip.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub smoothing: Option<Smoothing>,
 }
 
+impl TomatoParams {
+    pub fn new(tau: f64) -> Self {
+        Self {
+            tau,
+            noise: NoisePolicy::default(),
+            smoothing: None,
+        }
+    }
+
+    pub fn with_noise(mut self, noise: NoisePolicy) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = Some(smoothing);
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TomatoError> {
+        if self.tau.is_nan() || self.tau < 0.0 {
+            return Err(TomatoError::InvalidTau);
+        }
+        if let Some(smoothing) = &self.smoothing {
+            smoothing.validate()?;
+        }
+        self.noise.validate()
+    }
+}
+
+/// `cluster_of[v]` is the mode of the cluster of v, or `NOISE`. `modes` lists only modes of
+/// clusters that keep at least one point.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TomatoResult {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_fields::labels"))]
     pub cluster_of: Vec<usize>,
     pub modes: Vec<usize>,
+    pub diagram: Vec<PersistencePair>,
+}
+
+impl TomatoResult {
+    #[inline]
+    pub fn is_noise(&self, v: usize) -> bool {
+        self.cluster_of[v] == NOISE
+    }
+
+    pub fn n_noise(&self) -> usize {
+        self.cluster_of.iter().filter(|&&m| m == NOISE).count()
+    }
 }
 
+/// With `params.smoothing`, the density is first smoothed over `graph` as by `smooth_density`
+/// and the clustering runs on the smoothed values.
 pub fn tomato_cluster(
     graph: &Graph,
     density: &[f64],
     params: TomatoParams,
 ) -> Result<TomatoResult, TomatoError> {
+    match &params.smoothing {
+        Some(smoothing) => {
+            validate_density(density)?;
+            cluster(graph, &smooth_density(graph, density, smoothing)?, &params)
+        }
+        None => cluster(graph, density, &params),
+    }
+}
+
+// `tomato_cluster` on a density that is already smoothed if the params ask for it.
+pub(crate) fn cluster(graph: &Graph, density: &[f64], params: &TomatoParams) -> Result<TomatoResult, TomatoError> {
     validate_density(density)?;
     if density.len() != graph.n() {
         return Err(TomatoError::DensityLengthMismatch);
     }
-    if !(params.tau >= 0.0) {
-        return Err(TomatoError::InvalidTau);
-    }
+    params.validate()?;
 
-    let tau = params.tau;
     let n = graph.n();
     let ord = vertices_desc_by_density(density);
+    let mut cluster_of = protected_merges(graph, density, &ord, params.tau).cluster_of;
+
+    let diagram = diagram_in_order(graph, density, &ord);
+    if !params.noise.is_none() {
+        let mut prominence = vec![0.0; n];
+        for p in &diagram {
+            prominence[p.mode] = p.prominence();
+        }
+        apply_noise(&params.noise, density, &prominence, &mut cluster_of);
+    }
+
+    let mut modes: Vec<usize> = Vec::new();
+    for &m in &cluster_of {
+        if m != NOISE && !modes.contains(&m) {
+            modes.push(m);
+        }
+    }
+
+    modes.sort_by(|&a, &b| {
+        let fa = density[a];
+        let fb = density[b];
+        if fa > fb {
+            std::cmp::Ordering::Less
+        } else if fa < fb {
+            std::cmp::Ordering::Greater
+        } else {
+            a.cmp(&b)
+        }
+    });
+
+    Ok(TomatoResult {
+        cluster_of,
+        modes,
+        diagram,
+    })
+}
+
+/// The merges of the ToMATo union-find at one tau.
+///
+/// `parent[v]` is the mode whose component v joined, or into which the component of mode v
+/// merged, and `saddle[v]` the vertex where that happened. Modes that survive tau, including
+/// protected roots, are their own parent.
+#[derive(Debug, Clone)]
+pub(crate) struct Merges {
+    pub(crate) cluster_of: Vec<usize>,
+    pub(crate) parent: Vec<usize>,
+    pub(crate) saddle: Vec<Option<usize>>,
+}
+
+impl Merges {
+    /// See `MergeTree::attach`, with the components of this cut.
+    pub(crate) fn attach(&self, density: &[f64], neighbors: &[usize], level: f64) -> Option<usize> {
+        attach_below(density, &self.parent, &self.saddle, neighbors, level)
+    }
+}
+
+// Vertices are activated in `ord`. A component whose mode lives at least tau when it first
+// meets a higher component is protected: it is never merged afterwards, though it still
+// absorbs lower components.
+pub(crate) fn protected_merges(graph: &Graph, density: &[f64], ord: &[usize], tau: f64) -> Merges {
+    let n = graph.n();
     let mut uf = UfTomato::new(n);
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut saddle: Vec<Option<usize>> = vec![None; n];
 
     let mut uniq_roots: Vec<usize> = Vec::new();
 
-    for &v in &ord {
+    for &v in ord {
         uf.activate(v);
 
         uniq_roots.clear();
@@ -69,7 +276,7 @@ pub fn tomato_cluster(
                 continue;
             }
             let ru = uf.find(u);
-            if !uniq_roots.iter().any(|&x| x == ru) {
+            if !uniq_roots.contains(&ru) {
                 uniq_roots.push(ru);
             }
         }
@@ -111,6 +318,8 @@ pub fn tomato_cluster(
             let lifetime = density[m] - fv;
 
             if lifetime < tau {
+                parent[m] = winner_mode;
+                saddle[m] = Some(v);
                 let w_after = uf.union_survivor(density, w, r);
                 winner_root = w_after;
             } else {
@@ -119,30 +328,49 @@ pub fn tomato_cluster(
         }
     }
 
-    let mut cluster_of = vec![0usize; n];
-    for v in 0..n {
-        let r = uf.find(v);
-        cluster_of[v] = uf.mode_of_root(r);
+    let cluster_of: Vec<usize> = (0..n)
+        .map(|v| {
+            let r = uf.find(v);
+            uf.mode_of_root(r)
+        })
+        .collect();
+
+    Merges {
+        cluster_of,
+        parent,
+        saddle,
     }
+}
 
-    let mut modes: Vec<usize> = Vec::new();
-    for &m in &cluster_of {
-        if !modes.iter().any(|&x| x == m) {
-            modes.push(m);
+// Points that fail the policy get `NOISE`, see `NoisePolicy`. `prominence` is indexed by vertex.
+pub(crate) fn apply_noise(noise: &NoisePolicy, density: &[f64], prominence: &[f64], cluster_of: &mut [usize]) {
+    if let Some(min_prominence) = noise.min_prominence {
+        for m in cluster_of.iter_mut() {
+            if *m != NOISE && prominence[*m] < min_prominence {
+                *m = NOISE;
+            }
         }
     }
 
-    modes.sort_by(|&a, &b| {
-        let fa = density[a];
-        let fb = density[b];
-        if fa > fb {
-            std::cmp::Ordering::Less
-        } else if fa < fb {
-            std::cmp::Ordering::Greater
-        } else {
-            a.cmp(&b)
+    if let Some(min_density) = noise.min_density {
+        for v in 0..cluster_of.len() {
+            if density[v] < min_density {
+                cluster_of[v] = NOISE;
+            }
         }
-    });
+    }
 
-    Ok(TomatoResult { cluster_of, modes })
-}
\ No newline at end of file
+    if let Some(min_size) = noise.min_cluster_size {
+        let mut size = vec![0usize; cluster_of.len()];
+        for &m in cluster_of.iter() {
+            if m != NOISE {
+                size[m] += 1;
+            }
+        }
+        for m in cluster_of.iter_mut() {
+            if *m != NOISE && size[*m] < min_size {
+                *m = NOISE;
+            }
+        }
+    }
+}
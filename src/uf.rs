@@ -65,6 +65,11 @@ impl UfTomato {
         self.mode[r]
     }
 
+    #[inline]
+    pub fn size_of_root(&self, r: usize) -> usize {
+        self.size[r]
+    }
+
     pub fn find(&mut self, mut v: usize) -> usize {
         while self.parent[v] != v {
             let p = self.parent[v];
@@ -103,4 +108,19 @@ impl UfTomato {
 
         new_root
     }
+
+    /// Merges the sets of `a` and `b` by size alone, for plain connectivity where modes do not
+    /// matter, and returns the root of the merged set.
+    pub fn union(&mut self, a: usize, b: usize) -> usize {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return a;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        a
+    }
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tomato::backend::{BruteBackend, HnswBackend, HnswParams, PrecomputedBackend};
+use tomato::pipeline::{
+    density_at_point, estimate_density, estimate_density_with_knn, AdaptiveKde, DensitySpec, KnnTable,
+};
+use tomato::TomatoError;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-12 * a.abs().max(1.0)
+}
+
+fn adaptive(k: usize, bandwidth2: f64, method: AdaptiveKde) -> DensitySpec {
+    DensitySpec::KdeGaussianAdaptive { k, bandwidth2, method }
+}
+
+// A dense square and a sparse one.
+fn blobs() -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(3);
+    (0..300)
+        .map(|i| {
+            let (c, s) = if i % 3 == 0 { (8.0, 3.0) } else { (0.0, 1.0) };
+            vec![c + s * rng.random_range(-1.0..1.0), c + s * rng.random_range(-1.0..1.0)]
+        })
+        .collect()
+}
+
+#[test]
+fn bandwidths_follow_the_kth_neighbor_distance() {
+    let xs = [0.0, 1.0, 3.0, 7.0];
+    let b = BruteBackend::new(xs.iter().map(|&x| vec![x]).collect()).unwrap();
+
+    // in one dimension h² = h0 r, here h0 = 1 and r = 1, 1, 2, 4
+    let balloon = estimate_density(&b, adaptive(1, 1.0, AdaptiveKde::Balloon)).unwrap();
+    assert!(close(balloon[0], (-0.5f64).exp()));
+    assert!(close(balloon[2], (-1.0f64).exp() / 2f64.sqrt()));
+    assert!(close(balloon[3], 0.5 * (-2.0f64).exp()));
+
+    let sample = estimate_density(&b, adaptive(1, 1.0, AdaptiveKde::SamplePoint)).unwrap();
+    assert!(close(sample[2], (-2.0f64).exp()));
+    assert!(close(sample[3], (-4.0f64).exp() / 2f64.sqrt()));
+}
+
+#[test]
+fn constant_spacing_gives_back_the_fixed_kernel() {
+    // every point of a regular polygon has the same neighbor distances
+    let n = 40;
+    let points: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            let t = 2.0 * std::f64::consts::PI * i as f64 / n as f64;
+            vec![t.cos(), t.sin()]
+        })
+        .collect();
+    let b = BruteBackend::new(points).unwrap();
+    let r2 = 2.0 - 2.0 * (4.0 * std::f64::consts::PI / n as f64).cos();
+
+    let fixed = estimate_density(&b, DensitySpec::KdeGaussianKnn { k: 4, bandwidth2: r2 }).unwrap();
+    for method in [AdaptiveKde::Balloon, AdaptiveKde::SamplePoint] {
+        let f = estimate_density(&b, adaptive(4, r2, method)).unwrap();
+        assert!(f.iter().zip(&fixed).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+}
+
+#[test]
+fn new_points_match_stored_points() {
+    let mut points = blobs();
+    let b = BruteBackend::new(points.clone()).unwrap();
+    let q = vec![0.3, -0.2];
+    let spec = adaptive(10, 0.2, AdaptiveKde::Balloon);
+    let at = density_at_point(&b, &spec, &q).unwrap();
+
+    points.push(q);
+    let with_q = estimate_density(&BruteBackend::new(points).unwrap(), spec).unwrap();
+    assert!(close(at, with_q[300]));
+
+    // the sample point kernels come from the stored points alone
+    let spec = adaptive(10, 0.2, AdaptiveKde::SamplePoint);
+    let stored = estimate_density(&b, spec.clone()).unwrap();
+    let far = density_at_point(&b, &spec, &[100.0, 100.0]).unwrap();
+    assert!(far >= 0.0 && far < stored.iter().cloned().fold(f64::INFINITY, f64::min));
+}
+
+#[test]
+fn hnsw_densities_come_from_its_knn_table() {
+    let points = blobs();
+    let brute = BruteBackend::new(points.clone()).unwrap();
+    let hnsw = HnswBackend::new(points, HnswParams::default()).unwrap();
+    let knn = KnnTable::build(&hnsw, 20);
+
+    for method in [AdaptiveKde::Balloon, AdaptiveKde::SamplePoint] {
+        let spec = adaptive(12, 0.1, method);
+        let from_table = estimate_density_with_knn(&hnsw, spec.clone(), &knn).unwrap();
+        assert_eq!(estimate_density(&hnsw, spec.clone()).unwrap(), from_table);
+
+        // the index is approximate, a missed neighbor also moves the sample point density of
+        // its neighbors, but the typical point agrees with brute force
+        let exact = estimate_density(&brute, spec).unwrap();
+        let mut rel: Vec<f64> = exact.iter().zip(&from_table).map(|(a, b)| (a - b).abs() / a).collect();
+        rel.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(rel[150] < 1e-4, "median relative difference {}", rel[150]);
+    }
+}
+
+#[test]
+fn adaptive_kde_rejects_bad_specs() {
+    let b = BruteBackend::new(blobs()).unwrap();
+    assert!(matches!(
+        estimate_density(&b, adaptive(0, 1.0, AdaptiveKde::Balloon)),
+        Err(TomatoError::InvalidGraph(_))
+    ));
+    assert!(estimate_density(&b, adaptive(5, 0.0, AdaptiveKde::SamplePoint)).is_err());
+    assert!(estimate_density(&b, adaptive(5, f64::INFINITY, AdaptiveKde::Balloon)).is_err());
+
+    let pre = PrecomputedBackend::from_condensed(3, vec![1.0, 2.0, 1.0]).unwrap();
+    assert!(estimate_density(&pre, adaptive(1, 1.0, AdaptiveKde::Balloon)).is_err());
+    let f = estimate_density(&pre.with_intrinsic_dim(1), adaptive(1, 1.0, AdaptiveKde::Balloon)).unwrap();
+    assert!(f.iter().all(|x| x.is_finite()));
+}
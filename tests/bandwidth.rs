@@ -0,0 +1,145 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tomato::backend::{BruteBackend, PrecomputedBackend};
+use tomato::pipeline::{
+    knn_bandwidth2, log_spaced, loo_bandwidth2, scott_bandwidth2, silverman_bandwidth2, BandwidthDiagnostics,
+};
+use tomato::TomatoError;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-12 * a.abs().max(1.0)
+}
+
+// Standard normal samples in d dimensions, scaled by sd.
+fn gaussian(n: usize, d: usize, sd: f64, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| {
+            (0..d)
+                .map(|_| {
+                    let u: f64 = rng.random_range(f64::EPSILON..1.0);
+                    let v: f64 = rng.random();
+                    sd * (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn line(n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|i| vec![i as f64]).collect()
+}
+
+#[test]
+fn rules_of_thumb_follow_their_formulas() {
+    let points = line(5);
+
+    let scott = scott_bandwidth2(&points).unwrap();
+    let sd = 2.5f64.sqrt();
+    let factor = 5f64.powf(-0.2);
+    assert!(close(scott.bandwidth2, (sd * factor).powi(2)));
+    assert_eq!(
+        scott.diagnostics,
+        BandwidthDiagnostics::Scott {
+            n: 5,
+            dim: 1,
+            sigma: sd,
+            factor
+        }
+    );
+
+    // the interquartile range 2 gives a smaller spread than the standard deviation
+    let silverman = silverman_bandwidth2(&points).unwrap();
+    let sigma = 2.0 / 1.349;
+    assert!(close(silverman.bandwidth2, (sigma * (4.0f64 / 15.0).powf(0.2)).powi(2)));
+
+    // both shrink with n and grow with the spread
+    let small = gaussian(100, 3, 1.0, 1);
+    let large = gaussian(10_000, 3, 1.0, 1);
+    let wide = gaussian(100, 3, 4.0, 1);
+    let h = |p: &[Vec<f64>]| scott_bandwidth2(p).unwrap().bandwidth2;
+    assert!(h(&large) < h(&small));
+    assert!(close(h(&wide), 16.0 * h(&small)));
+
+    assert!(matches!(scott_bandwidth2(&line(1)), Err(TomatoError::Bandwidth(_))));
+    assert!(matches!(silverman_bandwidth2(&[vec![1.0], vec![1.0]]), Err(TomatoError::Bandwidth(_))));
+    assert!(scott_bandwidth2(&[vec![1.0], vec![1.0, 2.0]]).is_err());
+}
+
+#[test]
+fn knn_heuristic_takes_the_median_kth_distance() {
+    let backend = BruteBackend::new(line(6)).unwrap();
+
+    let one = knn_bandwidth2(&backend, 1).unwrap();
+    assert_eq!(one.bandwidth2, 1.0);
+
+    let two = knn_bandwidth2(&backend, 2).unwrap();
+    assert_eq!(two.bandwidth2, 1.0);
+    assert_eq!(
+        two.diagnostics,
+        BandwidthDiagnostics::KnnDistance {
+            k: 2,
+            min: 1.0,
+            median: 1.0,
+            max: 4.0
+        }
+    );
+
+    assert!(knn_bandwidth2(&backend, 0).is_err());
+    let dup = BruteBackend::new(vec![vec![0.0]; 4]).unwrap();
+    assert!(matches!(knn_bandwidth2(&dup, 1), Err(TomatoError::Bandwidth(_))));
+}
+
+#[test]
+fn loo_likelihood_picks_an_interior_bandwidth() {
+    let points = gaussian(300, 2, 1.0, 7);
+    let backend = BruteBackend::new(points.clone()).unwrap();
+    let scott = scott_bandwidth2(&points).unwrap().bandwidth2;
+    let candidates = log_spaced(scott / 20.0, scott * 20.0, 25);
+    assert_eq!(candidates.len(), 25);
+    assert!(close(candidates[0], scott / 20.0) && close(candidates[24], scott * 20.0));
+
+    let full = loo_bandwidth2(&backend, &candidates, None).unwrap();
+    let BandwidthDiagnostics::LooLikelihood { scores, best, on_boundary } = &full.diagnostics else {
+        panic!("unexpected diagnostics");
+    };
+    assert!(!on_boundary);
+    assert_eq!(scores.len(), 25);
+    assert_eq!(scores[*best].0, full.bandwidth2);
+    assert!(scores.iter().all(|s| s.1 <= scores[*best].1));
+    // the optimum is of the order of the rule of thumb
+    assert!(full.bandwidth2 > scott / 10.0 && full.bandwidth2 < scott * 10.0);
+
+    // every neighbor in the table is the same as the full sum
+    let knn = loo_bandwidth2(&backend, &candidates, Some(299)).unwrap();
+    assert_eq!(knn.bandwidth2, full.bandwidth2);
+
+    let narrow = loo_bandwidth2(&backend, &[scott * 100.0, scott * 200.0], None).unwrap();
+    assert!(matches!(
+        narrow.diagnostics,
+        BandwidthDiagnostics::LooLikelihood { on_boundary: true, .. }
+    ));
+}
+
+#[test]
+fn log_spaced_handles_short_grids() {
+    assert!(log_spaced(0.5, 2.0, 0).is_empty());
+    assert_eq!(log_spaced(0.5, 2.0, 1), vec![0.5]);
+    let two = log_spaced(0.5, 2.0, 2);
+    assert!(close(two[0], 0.5) && close(two[1], 2.0));
+    let three = log_spaced(0.5, 2.0, 3);
+    assert!(close(three[1], 1.0));
+}
+
+#[test]
+fn loo_likelihood_rejects_bad_input() {
+    let backend = BruteBackend::new(line(5)).unwrap();
+    assert!(loo_bandwidth2(&backend, &[], None).is_err());
+    assert!(loo_bandwidth2(&backend, &[1.0, 0.0], None).is_err());
+    assert!(loo_bandwidth2(&backend, &[1.0], Some(0)).is_err());
+
+    let pre = PrecomputedBackend::from_condensed(3, vec![1.0, 2.0, 1.0]).unwrap();
+    assert!(matches!(loo_bandwidth2(&pre, &[1.0], None), Err(TomatoError::Bandwidth(_))));
+    assert!(loo_bandwidth2(&pre.with_intrinsic_dim(1), &[1.0], None).is_ok());
+}
@@ -0,0 +1,288 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tomato-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn tomato(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tomato")).args(args).output().unwrap()
+}
+
+// Two well separated blobs with an id column and a text column.
+fn blobs_csv(delimiter: char) -> String {
+    let mut s = format!("id{d}x{d}y{d}species\n", d = delimiter);
+    for i in 0..80 {
+        let c = if i % 2 == 0 { 0.0 } else { 6.0 };
+        let x = c + (i as f64 * 0.7).sin() * 0.6;
+        let y = c + (i as f64 * 1.3).cos() * 0.6;
+        s.push_str(&format!("{i}{d}{x}{d}{y}{d}s{s}\n", i = i, d = delimiter, x = x, y = y, s = i % 2));
+    }
+    s
+}
+
+fn labels(csv: &str) -> Vec<i64> {
+    csv.lines()
+        .skip(1)
+        .map(|l| l.split(',').nth(1).unwrap().parse().unwrap())
+        .collect()
+}
+
+#[test]
+fn cluster_writes_labels_density_and_modes() {
+    let dir = temp_dir("csv");
+    let input = dir.join("blobs.csv");
+    fs::write(&input, blobs_csv(',')).unwrap();
+
+    let out = tomato(&["cluster", input.to_str().unwrap(), "--columns", "x,y", "--tau", "inf", "--quiet"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let text = String::from_utf8(out.stdout).unwrap();
+    assert!(text.starts_with("row,label,density,mode\n"));
+
+    let l1 = labels(&text);
+    assert_eq!(l1.len(), 80);
+    for i in 0..80 {
+        assert_eq!(l1[i], l1[i % 2]);
+    }
+    assert_ne!(l1[0], l1[1]);
+
+    let tsv = dir.join("blobs.tsv");
+    fs::write(&tsv, blobs_csv('\t')).unwrap();
+    let out = tomato(&[
+        "cluster",
+        tsv.to_str().unwrap(),
+        "--columns",
+        "1,2",
+        "--backend",
+        "brute",
+        "--graph",
+        "rips",
+        "--radius2",
+        "1.0",
+        "--density",
+        "kde-full",
+        "--bandwidth2",
+        "0.5",
+        "--tau",
+        "inf",
+        "--quiet",
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let l2 = labels(&String::from_utf8(out.stdout).unwrap());
+    for i in 0..80 {
+        assert_eq!(l2[i] == l2[0], l1[i] == l1[0]);
+    }
+
+    let out = tomato(&[
+        "cluster",
+        input.to_str().unwrap(),
+        "--columns",
+        "x,y",
+        "--smooth",
+        "median",
+        "--smooth-iterations",
+        "2",
+        "--tau",
+        "inf",
+        "--quiet",
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(labels(&String::from_utf8(out.stdout).unwrap()), l1);
+
+    let out = tomato(&[
+        "cluster",
+        input.to_str().unwrap(),
+        "--columns",
+        "x,y",
+        "--weights",
+        "id",
+        "--zscore",
+        "--tau",
+        "inf",
+        "--quiet",
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let l3 = labels(&String::from_utf8(out.stdout).unwrap());
+    for i in 0..80 {
+        assert_eq!(l3[i] == l3[0], l1[i] == l1[0]);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cluster_writes_json_and_noise() {
+    let dir = temp_dir("json");
+    let input = dir.join("blobs.csv");
+    fs::write(&input, blobs_csv(',')).unwrap();
+    let output = dir.join("labels.json");
+
+    let out = tomato(&[
+        "cluster",
+        input.to_str().unwrap(),
+        "--columns",
+        "x,y",
+        "--zscore",
+        "--tau",
+        "0",
+        "--min-cluster-size",
+        "2",
+        "--output",
+        output.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("80 rows"));
+
+    let json = fs::read_to_string(&output).unwrap();
+    assert!(json.contains("\"n\": 80"));
+    assert!(json.contains("\"tau\": 0"));
+    assert!(json.contains("\"n_clusters\": 0"));
+    assert!(json.contains("\"label\": -1"));
+    assert!(json.contains("\"mode\": null"));
+    assert_eq!(json.matches("\"row\":").count(), 80);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cluster_reads_headerless_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tomato"))
+        .args(["cluster", "-", "--no-header", "--clusters", "2", "--quiet", "--format", "csv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut body = String::new();
+    for line in blobs_csv(',').lines().skip(1) {
+        let f: Vec<&str> = line.split(',').collect();
+        body.push_str(&format!("{},{}\n", f[1], f[2]));
+    }
+    child.stdin.take().unwrap().write_all(body.as_bytes()).unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let labels = labels(&String::from_utf8(out.stdout).unwrap());
+    assert_eq!(labels.len(), 80);
+    assert!(labels.iter().all(|&l| l == 0 || l == 1));
+}
+
+#[test]
+fn cluster_reports_bad_input() {
+    let dir = temp_dir("bad");
+    let input = dir.join("blobs.csv");
+    fs::write(&input, blobs_csv(',')).unwrap();
+    let path = input.to_str().unwrap();
+
+    let out = tomato(&["cluster", path]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not a number"));
+
+    let out = tomato(&["cluster", path, "--columns", "x,z"]);
+    assert_eq!(out.status.code(), Some(1));
+
+    let out = tomato(&["cluster", path, "--graph", "rips"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--radius2"));
+
+    let out = tomato(&["cluster", path, "--bogus"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    let out = tomato(&["cluster", path, "--density", "kde-full", "--bandwidth2", "1", "--kernel", "boxcar"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown kernel"));
+
+    let out = tomato(&["cluster", path, "--smooth", "weighted-mean"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--self-weight"));
+
+    let out = tomato(&["cluster", path, "--columns", "x,y", "--weights", "species"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not a number"));
+
+    let out = tomato(&["cluster", dir.join("missing.csv").to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+
+    let out = tomato(&["--help"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("usage: tomato cluster"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Two dense groups on a line joined by a sparse bridge, so the knn graph is connected.
+fn bridge_csv() -> String {
+    let mut s = String::from("x,y\n");
+    for i in 0..60 {
+        s.push_str(&format!("{},0\n", i as f64 * 0.02));
+    }
+    for i in 0..28 {
+        s.push_str(&format!("{},0\n", 1.3 + i as f64 * 0.13));
+    }
+    for i in 0..60 {
+        s.push_str(&format!("{},0\n", 5.0 + i as f64 * 0.02));
+    }
+    s
+}
+
+#[test]
+fn diagram_writes_pairs_and_svg() {
+    let dir = temp_dir("diagram");
+    let input = dir.join("bridge.csv");
+    fs::write(&input, bridge_csv()).unwrap();
+    let svg = dir.join("diagram.svg");
+
+    let out = tomato(&[
+        "diagram",
+        input.to_str().unwrap(),
+        "--graph-k",
+        "8",
+        "--density",
+        "kde-knn",
+        "--density-k",
+        "20",
+        "--bandwidth2",
+        "0.05",
+        "--fraction",
+        "0.3",
+        "--svg",
+        svg.to_str().unwrap(),
+    ]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("suggested tau"));
+
+    let text = String::from_utf8(out.stdout).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("mode,birth,death,prominence,saddle"));
+    let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+    assert!(rows.len() >= 2);
+    assert_eq!(rows.iter().filter(|r| r[3] == "inf").count(), 1);
+    assert_eq!(rows[0][3], "inf");
+    for r in &rows[1..] {
+        let birth: f64 = r[1].parse().unwrap();
+        let death: f64 = r[2].parse().unwrap();
+        let prominence: f64 = r[3].parse().unwrap();
+        assert!(death <= birth);
+        assert_eq!(prominence, birth - death);
+    }
+
+    let drawing = fs::read_to_string(&svg).unwrap();
+    assert!(drawing.starts_with("<svg"));
+    assert!(drawing.contains("class=\"diagonal\""));
+    assert!(drawing.contains("class=\"tau\""));
+    assert_eq!(drawing.matches("<circle").count(), rows.len() - 1);
+    assert_eq!(drawing.matches("<polygon").count(), 1);
+
+    let out = tomato(&["diagram", input.to_str().unwrap(), "--tau", "inf", "--quiet"]);
+    assert!(out.status.success());
+    assert!(out.stderr.is_empty());
+
+    let out = tomato(&["diagram", input.to_str().unwrap(), "--min-density", "1"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+use tomato::backend::{BruteBackend, PrecomputedBackend};
+use tomato::pipeline::{density_at_point, estimate_density, estimate_density_with_knn, DensitySpec, KnnTable};
+use tomato::TomatoError;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-12 * a.abs().max(1.0)
+}
+
+fn dtm(k: usize, p: f64) -> DensitySpec {
+    DensitySpec::Dtm { k, p, eps: 0.0 }
+}
+
+fn cloud() -> BruteBackend {
+    let pts = (0..200)
+        .map(|i| {
+            let t = i as f64;
+            vec![(t * 0.37).sin() * (1.0 + t / 100.0), (t * 0.91).cos()]
+        })
+        .collect();
+    BruteBackend::new(pts).unwrap()
+}
+
+#[test]
+fn dtm_averages_powers_of_the_neighbor_distances() {
+    let b = BruteBackend::new(vec![vec![0.0], vec![1.0], vec![3.0]]).unwrap();
+
+    // point 0 has its neighbors at 1 and 3
+    let rms = estimate_density(&b, dtm(2, 2.0)).unwrap();
+    assert!(close(rms[0], -0.5 * 5f64.ln()));
+    let mean = estimate_density(&b, dtm(2, 1.0)).unwrap();
+    assert!(close(mean[0], -2f64.ln()));
+
+    // a new point at 2 sees 1 and 3 at distance 1
+    assert!(close(density_at_point(&b, &dtm(2, 2.0), &[2.0]).unwrap(), 0.0));
+}
+
+#[test]
+fn dtm_lies_between_the_nearest_and_farthest_neighbor() {
+    let b = cloud();
+    let knn_log = |k| estimate_density(&b, DensitySpec::KnnLog { k, eps: 0.0 }).unwrap();
+    let nearest = knn_log(1);
+    let farthest = knn_log(10);
+
+    // with one neighbor every p agrees with KnnLog
+    for p in [0.5, 1.0, 2.0, 4.0] {
+        let one = estimate_density(&b, dtm(1, p)).unwrap();
+        assert!(one.iter().zip(&nearest).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+
+    // power means grow with p, so densities shrink, and stay above the farthest neighbor
+    let f1 = estimate_density(&b, dtm(10, 1.0)).unwrap();
+    let f2 = estimate_density(&b, dtm(10, 2.0)).unwrap();
+    let f8 = estimate_density(&b, dtm(10, 8.0)).unwrap();
+    for i in 0..200 {
+        assert!(f1[i] >= f2[i] - 1e-12 && f2[i] >= f8[i] - 1e-12);
+        assert!(f8[i] >= farthest[i] - 1e-12 && f1[i] <= nearest[i] + 1e-12);
+    }
+}
+
+#[test]
+fn dtm_reads_a_shared_knn_table() {
+    let b = cloud();
+    let knn = KnnTable::build(&b, 20);
+    let spec = dtm(12, 2.0);
+    assert_eq!(
+        estimate_density_with_knn(&b, spec.clone(), &knn).unwrap(),
+        estimate_density(&b, spec).unwrap()
+    );
+    assert!(estimate_density_with_knn(&b, dtm(25, 2.0), &knn).is_err());
+}
+
+#[test]
+fn dtm_rejects_bad_specs() {
+    let b = cloud();
+    for spec in [dtm(0, 2.0), dtm(5, 0.0), dtm(5, f64::INFINITY), dtm(5, f64::NAN)] {
+        assert!(matches!(estimate_density(&b, spec), Err(TomatoError::InvalidGraph(_))));
+    }
+
+    let pre = PrecomputedBackend::from_condensed(3, vec![1.0, 2.0, 1.0]).unwrap();
+    assert!(estimate_density(&pre, dtm(1, 2.0)).is_err());
+    assert!(estimate_density(&pre.with_intrinsic_dim(2), dtm(2, 2.0)).is_ok());
+}
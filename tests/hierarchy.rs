@@ -0,0 +1,187 @@
+use proptest::prelude::*;
+use tomato::graph::Graph;
+use tomato::hierarchy::MergeTree;
+use tomato::tomato::{tomato_cluster, TomatoParams};
+
+fn random_graph() -> impl Strategy<Value = (Vec<Vec<usize>>, Vec<f64>)> {
+    (1usize..24).prop_flat_map(|n| {
+        (
+            proptest::collection::vec((0..n, 0..n), 0..3 * n),
+            proptest::collection::vec(0u8..8, n),
+        )
+            .prop_map(move |(edges, f)| {
+                let mut adj: Vec<Vec<usize>> = vec![Vec::new(); n];
+                for (u, v) in edges {
+                    adj[u].push(v);
+                }
+                let adj = Graph::symmetrize_and_dedup(adj);
+                let f = f.into_iter().map(|x| x as f64).collect();
+                (adj, f)
+            })
+    })
+}
+
+// The protected root union-find of the original tomato_cluster, kept apart from the crate.
+fn reference_labels(adj: &[Vec<usize>], f: &[f64], tau: f64) -> Vec<usize> {
+    let n = f.len();
+    let higher = |a: usize, b: usize| f[a] > f[b] || (f[a] == f[b] && a < b);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| if higher(a, b) { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater });
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mode: Vec<usize> = (0..n).collect();
+    let mut protected = vec![false; n];
+    let mut active = vec![false; n];
+    fn find(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+
+    for &v in &order {
+        active[v] = true;
+        let mut roots = vec![v];
+        for &u in &adj[v] {
+            if active[u] {
+                let r = find(&mut parent, u);
+                if !roots.contains(&r) {
+                    roots.push(r);
+                }
+            }
+        }
+        let mut winner = roots[0];
+        for &r in &roots[1..] {
+            if higher(mode[r], mode[winner]) {
+                winner = r;
+            }
+        }
+        for &r in &roots {
+            if r == winner || protected[r] {
+                continue;
+            }
+            if f[mode[r]] - f[v] < tau {
+                parent[r] = winner;
+            } else {
+                protected[r] = true;
+            }
+        }
+    }
+    (0..n).map(|v| mode[find(&mut parent, v)]).collect()
+}
+
+// Every cluster induces a connected subgraph.
+fn clusters_are_connected(adj: &[Vec<usize>], labels: &[usize]) -> bool {
+    let n = labels.len();
+    let mut seen = vec![false; n];
+    let mut roots = 0;
+    let mut n_labels: Vec<usize> = labels.to_vec();
+    n_labels.sort();
+    n_labels.dedup();
+    for s in 0..n {
+        if seen[s] {
+            continue;
+        }
+        roots += 1;
+        seen[s] = true;
+        let mut stack = vec![s];
+        while let Some(v) = stack.pop() {
+            for &u in &adj[v] {
+                if !seen[u] && labels[u] == labels[v] {
+                    seen[u] = true;
+                    stack.push(u);
+                }
+            }
+        }
+    }
+    roots == n_labels.len()
+}
+
+fn taus(tree: &MergeTree) -> Vec<f64> {
+    let mut taus = vec![0.0, 0.5, 1e100];
+    for p in tree.diagram() {
+        let pr = p.prominence();
+        if pr.is_finite() {
+            taus.push(pr);
+            taus.push(pr + 0.25);
+        }
+    }
+    taus.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    taus
+}
+
+proptest! {
+    #[test]
+    fn hierarchy_cut_matches_tomato_cluster((adj, f) in random_graph()) {
+        let g = Graph::new(adj.clone()).unwrap();
+        let tree = MergeTree::new(&g, &f).unwrap();
+
+        for tau in taus(&tree) {
+            let res = tomato_cluster(&g, &f, TomatoParams::new(tau)).unwrap();
+            let expected = reference_labels(&adj, &f, tau);
+            prop_assert_eq!(&res.cluster_of, &expected);
+            prop_assert!(clusters_are_connected(&adj, &expected));
+            prop_assert_eq!(tree.labels_at(tau).unwrap(), res.cluster_of);
+            prop_assert_eq!(tree.modes_at(tau).unwrap(), res.modes.clone());
+            prop_assert_eq!(tree.n_clusters_at(tau).unwrap(), res.modes.len());
+        }
+    }
+
+    // Only the modes are nested: a vertex between a mode that survives a small tau and a
+    // higher one can join either, see cuts_are_not_nested.
+    #[test]
+    fn hierarchy_modes_are_nested((adj, f) in random_graph()) {
+        let g = Graph::new(adj).unwrap();
+        let tree = MergeTree::new(&g, &f).unwrap();
+        let taus = taus(&tree);
+
+        for w in taus.windows(2) {
+            let fine = tree.modes_at(w[0]).unwrap();
+            let coarse = tree.modes_at(w[1]).unwrap();
+            prop_assert!(coarse.iter().all(|m| fine.contains(m)));
+        }
+    }
+}
+
+#[test]
+fn cuts_are_not_nested() {
+    // the path 0 - 3 - 4 - 1 - 2: 4 survives tau = 1 at 3 and 1 then joins 2, at tau = 2 4
+    // merges into 0 and 1 joins 0
+    let g = Graph::new(vec![vec![3], vec![2, 4], vec![1], vec![0, 4], vec![1, 3]]).unwrap();
+    let f = vec![5.0, 0.0, 4.0, 1.0, 2.5];
+    let tree = MergeTree::new(&g, &f).unwrap();
+    assert_eq!(tree.labels_at(1.0).unwrap(), vec![0, 2, 2, 0, 4]);
+    assert_eq!(tree.labels_at(2.0).unwrap(), vec![0, 0, 2, 0, 0]);
+}
+
+#[test]
+fn hierarchy_batched_union_regression() {
+    let g = Graph::new(vec![vec![3], vec![3], vec![3], vec![1, 0, 2]]).unwrap();
+    let f = vec![4.0, 5.0, 10.0, 0.0];
+    let tree = MergeTree::new(&g, &f).unwrap();
+
+    let labels = tree.labels_at(4.5).unwrap();
+    assert_eq!(labels[0], labels[2]);
+    assert_ne!(labels[1], labels[2]);
+    assert_eq!(tree.n_clusters_at(4.5).unwrap(), 2);
+    assert_eq!(tree.n_clusters_at(5.5).unwrap(), 1);
+    assert!(tree.labels_at(-1.0).is_err());
+}
+
+#[test]
+fn protected_mode_keeps_the_components_it_meets() {
+    // 1 survives tau = 5 at 3, so 2 meets the component of 1 at 4 and joins it, not 0
+    let adj = vec![vec![3], vec![3, 4], vec![4], vec![0, 1], vec![1, 2]];
+    let g = Graph::new(adj.clone()).unwrap();
+    let f = vec![10.0, 8.0, 5.5, 2.0, 1.0];
+
+    let res = tomato_cluster(&g, &f, TomatoParams::new(5.0)).unwrap();
+    assert_eq!(res.cluster_of, vec![0, 1, 1, 0, 1]);
+    assert_eq!(res.modes, vec![0, 1]);
+    assert_eq!(reference_labels(&adj, &f, 5.0), res.cluster_of);
+
+    let tree = MergeTree::new(&g, &f).unwrap();
+    assert_eq!(tree.labels_at(5.0).unwrap(), res.cluster_of);
+    assert_eq!(tree.modes_at(5.0).unwrap(), res.modes);
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::PathBuf;
+
+use tomato::backend::{AnnBackend, HnswBackend, HnswIndexFile, HnswParams, Manhattan};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tomato-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn points(n: usize, dim: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..dim).map(|j| ((i * (j + 3)) as f64 * 0.173).sin() * 4.0).collect())
+        .collect()
+}
+
+#[test]
+fn save_and_load_roundtrip() {
+    let dir = temp_dir("roundtrip");
+    let params = HnswParams {
+        ef_search: 80,
+        ..HnswParams::default()
+    };
+    let backend = HnswBackend::new(points(400, 3), params).unwrap();
+    backend.save(dir.join("index")).unwrap();
+
+    let file = HnswIndexFile::open(dir.join("index")).unwrap();
+    assert_eq!(file.params().ef_search, 80);
+    let loaded = file.load().unwrap();
+    assert_eq!(loaded.len(), 400);
+    assert_eq!(loaded.dim(), 3);
+    assert_eq!(loaded.params().ef_search, 80);
+    assert_eq!(loaded.params().max_nb_connection, backend.params().max_nb_connection);
+
+    for i in (0..400).step_by(17) {
+        assert_eq!(loaded.knn_indices_dist2(i, 8), backend.knn_indices_dist2(i, 8));
+    }
+    let q = [0.5, -1.0, 2.0];
+    assert_eq!(loaded.knn_point_dist2(&q, 5).unwrap(), backend.knn_point_dist2(&q, 5).unwrap());
+
+    let empty = HnswBackend::new(vec![], HnswParams::default()).unwrap();
+    empty.save(dir.join("empty")).unwrap();
+    assert!(HnswIndexFile::open(dir.join("empty")).unwrap().load().unwrap().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn load_rejects_inconsistent_files() {
+    let dir = temp_dir("inconsistent");
+    HnswBackend::new(points(300, 2), HnswParams::default())
+        .unwrap()
+        .save(dir.join("a"))
+        .unwrap();
+    HnswBackend::new(points(250, 2), HnswParams::default())
+        .unwrap()
+        .save(dir.join("b"))
+        .unwrap();
+
+    assert!(HnswIndexFile::open(dir.join("missing")).is_err());
+    assert!(HnswIndexFile::open(dir.join("a")).unwrap().load_with_metric(Manhattan).is_err());
+
+    fs::copy(dir.join("b.tomato"), dir.join("a.tomato")).unwrap();
+    assert!(HnswIndexFile::open(dir.join("a")).unwrap().load().is_err());
+
+    let bytes = fs::read(dir.join("b.tomato")).unwrap();
+    fs::write(dir.join("b.tomato"), &bytes[..bytes.len() - 4]).unwrap();
+    assert!(HnswIndexFile::open(dir.join("b")).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+use proptest::prelude::*;
+use tomato::backend::{AnnBackend, BruteBackend, KdTreeBackend};
+use tomato::pipeline::{build_graph, estimate_density, DensitySpec, GraphSpec};
+
+fn grid_points() -> impl Strategy<Value = Vec<Vec<f64>>> {
+    (1usize..4).prop_flat_map(|d| {
+        proptest::collection::vec(proptest::collection::vec(-4i32..5, d), 1..90)
+            .prop_map(|pts| {
+                pts.into_iter()
+                    .map(|p| p.into_iter().map(|x| x as f64 * 0.5).collect())
+                    .collect()
+            })
+    })
+}
+
+proptest! {
+    #[test]
+    fn kdtree_matches_brute(pts in grid_points()) {
+        let brute = BruteBackend::new(pts.clone()).unwrap();
+        let kd = KdTreeBackend::new(pts.clone()).unwrap();
+        let n = pts.len();
+
+        for i in 0..n {
+            for k in [1usize, 4, 17, n] {
+                prop_assert_eq!(kd.knn_indices_dist2(i, k), brute.knn_indices_dist2(i, k));
+            }
+            for radius2 in [0.0, 0.5, 2.25] {
+                let mut a = kd.range_dist2(i, radius2);
+                let mut b = brute.range_dist2(i, radius2);
+                a.sort_by_key(|x| x.0);
+                b.sort_by_key(|x| x.0);
+                prop_assert_eq!(a, b);
+            }
+        }
+
+        let q: Vec<f64> = (0..kd.dim()).map(|j| 0.3 * j as f64 - 0.2).collect();
+        prop_assert_eq!(kd.knn_point_dist2(&q, 7).unwrap(), brute.knn_point_dist2(&q, 7).unwrap());
+        let mut a = kd.range_point_dist2(&q, 3.0).unwrap();
+        a.sort_by_key(|x| x.0);
+        prop_assert_eq!(a, brute.range_point_dist2(&q, 3.0).unwrap());
+    }
+}
+
+#[test]
+fn kdtree_exact_pipeline_matches_brute() {
+    let pts: Vec<Vec<f64>> = (0..500)
+        .map(|i| vec![(i as f64 * 0.37).sin() * 3.0, (i as f64 * 0.11).cos(), (i as f64 * 0.05).sin()])
+        .collect();
+    let brute = BruteBackend::new(pts.clone()).unwrap();
+    let kd = KdTreeBackend::new(pts).unwrap();
+
+    let spec = GraphSpec::RipsBrute { radius2: 0.2 };
+    let gb = build_graph(&brute, spec.clone()).unwrap();
+    let gk = build_graph(&kd, spec).unwrap();
+    for i in 0..gb.n() {
+        assert_eq!(gb.neighbors(i), gk.neighbors(i));
+    }
+
+    let spec = DensitySpec::KdeGaussianKnn { k: 20, bandwidth2: 0.1 };
+    assert_eq!(estimate_density(&brute, spec.clone()).unwrap(), estimate_density(&kd, spec).unwrap());
+
+    // the full sum runs in range order, which differs between backends
+    let full = estimate_density(&brute, DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.1 }).unwrap();
+    let kd_full = estimate_density(&kd, DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.1 }).unwrap();
+    let knn = estimate_density(&brute, DensitySpec::KdeGaussianKnn { k: 499, bandwidth2: 0.1 }).unwrap();
+    for ((f, g), h) in full.iter().zip(&kd_full).zip(&knn) {
+        assert!((f - g).abs() < 1e-12 * f);
+        assert!((f - h).abs() < 1e-12 * f);
+    }
+}
@@ -0,0 +1,130 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tomato::backend::{BruteBackend, KdTreeBackend};
+use tomato::pipeline::{
+    density_at_point, estimate_density, run_pipeline, DensitySpec, GraphSpec, Kernel, PipelineParams,
+};
+use tomato::{TomatoError, TomatoParams};
+
+const KERNELS: [Kernel; 6] = [
+    Kernel::Gaussian,
+    Kernel::Epanechnikov,
+    Kernel::Tophat,
+    Kernel::Triweight,
+    Kernel::Laplacian,
+    Kernel::Cauchy,
+];
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9 * a.abs().max(1.0)
+}
+
+fn points(n: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| vec![rng.random_range(0.0..4.0), rng.random_range(0.0..4.0)])
+        .collect()
+}
+
+fn dist2(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+#[test]
+fn kernel_profiles() {
+    let w = |k: Kernel| [0.0, 0.25, 1.0, 4.0].map(|u2| k.weight(u2));
+    assert_eq!(w(Kernel::Tophat), [1.0, 1.0, 1.0, 0.0]);
+    assert_eq!(w(Kernel::Epanechnikov), [1.0, 0.75, 0.0, 0.0]);
+    assert_eq!(w(Kernel::Triweight), [1.0, 0.75f64.powi(3), 0.0, 0.0]);
+    assert_eq!(w(Kernel::Laplacian), [1.0, (-0.5f64).exp(), (-1.0f64).exp(), (-2.0f64).exp()]);
+    assert_eq!(w(Kernel::Cauchy), [1.0, 0.8, 0.5, 0.2]);
+    assert!(close(Kernel::Gaussian.weight(4.0), (-2.0f64).exp()));
+
+    let compact: Vec<bool> = KERNELS.iter().map(|k| k.is_compact()).collect();
+    assert_eq!(compact, vec![false, true, true, true, false, false]);
+}
+
+#[test]
+fn tophat_counts_points_within_the_radius() {
+    let pts = points(200, 1);
+    let b = BruteBackend::new(pts.clone()).unwrap();
+    let r2 = 0.3;
+    let f = estimate_density(&b, DensitySpec::KdeRange { kernel: Kernel::Tophat, bandwidth2: r2 }).unwrap();
+    for i in 0..pts.len() {
+        let count = (0..pts.len()).filter(|&j| j != i && dist2(&pts[i], &pts[j]) <= r2).count();
+        assert_eq!(f[i], count as f64);
+    }
+
+    // a point on the boundary counts, and a new point counts every stored point
+    let line = BruteBackend::new(vec![vec![0.0], vec![1.0], vec![2.0], vec![3.5]]).unwrap();
+    let spec = DensitySpec::KdeRange { kernel: Kernel::Tophat, bandwidth2: 1.0 };
+    assert_eq!(estimate_density(&line, spec.clone()).unwrap(), vec![1.0, 2.0, 1.0, 0.0]);
+    assert_eq!(density_at_point(&line, &spec, &[1.0]).unwrap(), 3.0);
+}
+
+#[test]
+fn range_sums_agree_with_full_knn_sums() {
+    let pts = points(150, 2);
+    let b = BruteBackend::new(pts.clone()).unwrap();
+    let kd = KdTreeBackend::new(pts).unwrap();
+    let h2 = 0.4;
+
+    for kernel in KERNELS {
+        let range = estimate_density(&b, DensitySpec::KdeRange { kernel, bandwidth2: h2 }).unwrap();
+        let all = estimate_density(&b, DensitySpec::KdeKnn { kernel, k: 149, bandwidth2: h2 }).unwrap();
+        let tree = estimate_density(&kd, DensitySpec::KdeRange { kernel, bandwidth2: h2 }).unwrap();
+        for i in 0..150 {
+            assert!(close(range[i], all[i]) && close(range[i], tree[i]), "{:?} at {}", kernel, i);
+        }
+    }
+
+    let same = |s: DensitySpec, t: DensitySpec| {
+        let (x, y) = (estimate_density(&b, s).unwrap(), estimate_density(&b, t).unwrap());
+        x.iter().zip(&y).all(|(x, y)| close(*x, *y))
+    };
+    assert!(same(
+        DensitySpec::KdeKnn { kernel: Kernel::Gaussian, k: 10, bandwidth2: h2 },
+        DensitySpec::KdeGaussianKnn { k: 10, bandwidth2: h2 }
+    ));
+    assert!(same(
+        DensitySpec::KdeRange { kernel: Kernel::Gaussian, bandwidth2: h2 },
+        DensitySpec::KdeGaussianFullBrute { bandwidth2: h2 }
+    ));
+}
+
+#[test]
+fn compact_kernels_cluster_two_blobs() {
+    let pts: Vec<Vec<f64>> = (0..120)
+        .map(|i| {
+            let c = if i % 2 == 0 { 0.0 } else { 6.0 };
+            vec![c + (i as f64 * 0.7).sin(), c + (i as f64 * 1.3).cos()]
+        })
+        .collect();
+    let b = BruteBackend::new(pts).unwrap();
+    for kernel in [Kernel::Epanechnikov, Kernel::Triweight] {
+        let out = run_pipeline(
+            &b,
+            PipelineParams {
+                graph: GraphSpec::Knn { k: 10, symmetrize: true },
+                density: DensitySpec::KdeRange { kernel, bandwidth2: 2.0 },
+                tomato: TomatoParams::new(f64::INFINITY),
+            },
+        )
+        .unwrap();
+        assert_eq!(out.tomato.modes.len(), 2);
+    }
+}
+
+#[test]
+fn kernel_specs_reject_bad_bandwidths() {
+    let b = BruteBackend::new(points(10, 3)).unwrap();
+    for spec in [
+        DensitySpec::KdeRange { kernel: Kernel::Tophat, bandwidth2: 0.0 },
+        DensitySpec::KdeRange { kernel: Kernel::Cauchy, bandwidth2: f64::NAN },
+        DensitySpec::KdeKnn { kernel: Kernel::Laplacian, k: 5, bandwidth2: -1.0 },
+        DensitySpec::KdeKnn { kernel: Kernel::Epanechnikov, k: 0, bandwidth2: 1.0 },
+    ] {
+        assert!(matches!(estimate_density(&b, spec), Err(TomatoError::InvalidGraph(_))));
+    }
+}
@@ -0,0 +1,143 @@
+use std::cell::Cell;
+
+use tomato::backend::{AnnBackend, BruteBackend};
+use tomato::pipeline::{
+    build_graph, estimate_density, run_pipeline, run_pipeline_with_knn, DensitySpec, GraphSpec, KnnTable,
+    PipelineParams,
+};
+use tomato::{tomato_cluster, TomatoError, TomatoParams};
+
+struct CountingBackend {
+    inner: BruteBackend,
+    knn_calls: Cell<usize>,
+}
+
+impl AnnBackend for CountingBackend {
+    fn dim(&self) -> usize {
+        self.inner.dim()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn knn_indices_dist2(&self, query_index: usize, k: usize) -> Vec<(usize, f64)> {
+        self.knn_calls.set(self.knn_calls.get() + 1);
+        self.inner.knn_indices_dist2(query_index, k)
+    }
+
+    fn knn_point_dist2(&self, point: &[f64], k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        self.inner.knn_point_dist2(point, k)
+    }
+
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        self.inner.range_dist2(query_index, radius2)
+    }
+}
+
+fn points() -> Vec<Vec<f64>> {
+    (0..150)
+        .map(|i| {
+            let c = if i % 3 == 0 { 5.0 } else { 0.0 };
+            vec![c + (i as f64 * 0.29).sin(), (i as f64 * 0.61).cos()]
+        })
+        .collect()
+}
+
+fn params() -> PipelineParams {
+    PipelineParams {
+        graph: GraphSpec::RipsFromKnnApprox {
+            k: 10,
+            radius2: 0.4,
+            symmetrize: true,
+        },
+        density: DensitySpec::KdeGaussianKnn { k: 15, bandwidth2: 0.3 },
+        tomato: TomatoParams::new(0.5),
+    }
+}
+
+#[test]
+fn pipeline_queries_each_point_once() {
+    let backend = CountingBackend {
+        inner: BruteBackend::new(points()).unwrap(),
+        knn_calls: Cell::new(0),
+    };
+    let p = params();
+    assert_eq!(p.knn_k(), Some(15));
+
+    let out = run_pipeline(&backend, p.clone()).unwrap();
+    assert_eq!(backend.knn_calls.get(), backend.len());
+
+    let graph = build_graph(&backend.inner, p.graph.clone()).unwrap();
+    let density = estimate_density(&backend.inner, p.density.clone()).unwrap();
+    let tomato = tomato_cluster(&graph, &density, p.tomato.clone()).unwrap();
+    for i in 0..graph.n() {
+        assert_eq!(out.graph.neighbors(i), graph.neighbors(i));
+    }
+    assert_eq!(out.density, density);
+    assert_eq!(out.tomato.cluster_of, tomato.cluster_of);
+
+    let full = PipelineParams {
+        graph: GraphSpec::RipsBrute { radius2: 0.4 },
+        density: DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.3 },
+        tomato: TomatoParams::new(0.5),
+    };
+    assert_eq!(full.knn_k(), None);
+    backend.knn_calls.set(0);
+    run_pipeline(&backend, full).unwrap();
+    assert_eq!(backend.knn_calls.get(), 0);
+}
+
+#[test]
+fn knn_table_is_reusable() {
+    let backend = BruteBackend::new(points()).unwrap();
+    let table = KnnTable::build(&backend, 20);
+    assert_eq!(table.k(), 20);
+    assert_eq!(table.n(), backend.len());
+    assert_eq!(table.neighbors(3, 5), &backend.knn_indices_dist2(3, 5)[..]);
+
+    let a = run_pipeline_with_knn(&backend, params(), &table).unwrap();
+    let b = run_pipeline(&backend, params()).unwrap();
+    assert_eq!(a.density, b.density);
+    assert_eq!(a.tomato.cluster_of, b.tomato.cluster_of);
+
+    let small = KnnTable::build(&backend, 12);
+    assert!(matches!(
+        run_pipeline_with_knn(&backend, params(), &small),
+        Err(TomatoError::InvalidGraph(_))
+    ));
+    let other = KnnTable::build(&BruteBackend::new(points()[..100].to_vec()).unwrap(), 20);
+    assert!(run_pipeline_with_knn(&backend, params(), &other).is_err());
+
+    let rows = table.clone().into_rows();
+    assert_eq!(KnnTable::from_rows(20, rows).unwrap(), table);
+    assert!(KnnTable::from_rows(1, vec![vec![(1, 0.5), (0, 1.0)], vec![]]).is_err());
+    assert!(KnnTable::from_rows(2, vec![vec![(0, 0.5)], vec![]]).is_err());
+    assert!(KnnTable::from_rows(2, vec![vec![(1, 0.5)], vec![(0, 0.5)]]).is_ok());
+    assert!(KnnTable::from_rows(2, vec![vec![(1, 0.5), (2, 0.25)], vec![], vec![]]).is_err());
+}
+
+#[test]
+fn invalid_params_fail_before_any_query() {
+    let backend = CountingBackend {
+        inner: BruteBackend::new(points()).unwrap(),
+        knn_calls: Cell::new(0),
+    };
+    let mut bad_graph = params();
+    bad_graph.graph = GraphSpec::RipsFromKnnApprox {
+        k: 10,
+        radius2: -1.0,
+        symmetrize: true,
+    };
+    assert!(matches!(run_pipeline(&backend, bad_graph), Err(TomatoError::InvalidGraph(_))));
+
+    let mut bad_density = params();
+    bad_density.density = DensitySpec::KdeGaussianKnn { k: 15, bandwidth2: 0.0 };
+    assert!(run_pipeline(&backend, bad_density).is_err());
+
+    let mut bad_tau = params();
+    bad_tau.tomato = TomatoParams::new(-1.0);
+    assert!(matches!(run_pipeline(&backend, bad_tau), Err(TomatoError::InvalidTau)));
+
+    assert_eq!(backend.knn_calls.get(), 0);
+}
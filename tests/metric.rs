@@ -0,0 +1,116 @@
+use proptest::prelude::*;
+use tomato::backend::{
+    AnnBackend, AxisMetric, BruteBackend, Chebyshev, Cosine, Euclidean, HnswBackend, HnswParams, KdTreeBackend,
+    Mahalanobis, Manhattan, Metric, Minkowski,
+};
+
+fn grid_points() -> impl Strategy<Value = Vec<Vec<f64>>> {
+    (1usize..4).prop_flat_map(|d| {
+        proptest::collection::vec(proptest::collection::vec(-4i32..5, d), 1..60)
+            .prop_map(|pts| {
+                pts.into_iter()
+                    .map(|p| p.into_iter().map(|x| x as f64 * 0.5).collect())
+                    .collect()
+            })
+    })
+}
+
+fn check_kdtree_matches_brute<M: AxisMetric>(pts: &[Vec<f64>], metric: M) -> Result<(), TestCaseError> {
+    let brute = BruteBackend::with_metric(pts.to_vec(), metric.clone()).unwrap();
+    let kd = KdTreeBackend::with_metric(pts.to_vec(), metric).unwrap();
+    let n = pts.len();
+    for i in 0..n {
+        for k in [1usize, 5, n] {
+            prop_assert_eq!(kd.knn_indices_dist2(i, k), brute.knn_indices_dist2(i, k));
+        }
+        for radius2 in [0.25, 2.25] {
+            let mut a = kd.range_dist2(i, radius2);
+            let mut b = brute.range_dist2(i, radius2);
+            a.sort_by_key(|x| x.0);
+            b.sort_by_key(|x| x.0);
+            prop_assert_eq!(a, b);
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn kdtree_matches_brute_under_axis_metrics(pts in grid_points()) {
+        check_kdtree_matches_brute(&pts, Manhattan)?;
+        check_kdtree_matches_brute(&pts, Chebyshev)?;
+        check_kdtree_matches_brute(&pts, Minkowski::new(3.0).unwrap())?;
+    }
+}
+
+#[test]
+fn metrics_report_squared_distances() {
+    let a = [1.0, -2.0, 0.5];
+    let b = [-1.0, 1.0, 0.5];
+    assert_eq!(Euclidean.dist2(&a, &b), 13.0);
+    assert_eq!(Manhattan.dist2(&a, &b), 25.0);
+    assert_eq!(Chebyshev.dist2(&a, &b), 9.0);
+    assert!((Minkowski::new(2.0).unwrap().dist2(&a, &b) - 13.0).abs() < 1e-12);
+    assert!((Minkowski::new(1.0).unwrap().dist2(&a, &b) - 25.0).abs() < 1e-12);
+
+    assert!(Cosine.dist2(&[1.0, 0.0], &[2.0, 0.0]).abs() < 1e-12);
+    assert!((Cosine.dist2(&[1.0, 0.0], &[0.0, 3.0]) - 1.0).abs() < 1e-12);
+    assert!((Cosine.dist2(&[1.0, 0.0], &[-1.0, 0.0]) - 4.0).abs() < 1e-12);
+    assert_eq!(Cosine.dist2(&[0.0, 0.0], &[1.0, 1.0]), 1.0);
+
+    assert!(Minkowski::new(0.5).is_err());
+    assert!(Minkowski::new(f64::NAN).is_err());
+}
+
+#[test]
+fn mahalanobis_whitens_the_covariance() {
+    let id = Mahalanobis::new(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+    let a = [0.3, -1.2];
+    let b = [2.0, 0.7];
+    assert!((id.dist2(&a, &b) - Euclidean.dist2(&a, &b)).abs() < 1e-12);
+
+    // x' S^-1 x with S = [[4, 2], [2, 3]], S^-1 = [[3, -2], [-2, 4]] / 8
+    let m = Mahalanobis::new(vec![vec![4.0, 2.0], vec![2.0, 3.0]]).unwrap();
+    let x = [1.0, 2.0];
+    let expected = (3.0 * 1.0 - 4.0 * 1.0 * 2.0 + 4.0 * 4.0) / 8.0;
+    assert!((m.dist2(&x, &[0.0, 0.0]) - expected).abs() < 1e-12);
+    assert!((m.dist2_f32(&[1.0, 2.0], &[0.0, 0.0]) as f64 - expected).abs() < 1e-5);
+
+    assert!(Mahalanobis::new(vec![vec![1.0, 2.0], vec![2.0, 1.0]]).is_err());
+    assert!(Mahalanobis::new(vec![vec![1.0, 0.5], vec![0.0, 1.0]]).is_err());
+    assert!(Mahalanobis::new(vec![vec![1.0, 0.0]]).is_err());
+
+    let pts = vec![vec![0.0, 0.0, 0.0], vec![1.0, 1.0, 1.0]];
+    assert!(BruteBackend::with_metric(pts, m).is_err());
+}
+
+#[test]
+fn hnsw_follows_the_metric() {
+    let pts: Vec<Vec<f64>> = (0..300)
+        .map(|i| {
+            let t = i as f64 * 0.021;
+            let r = 1.0 + (i % 7) as f64;
+            vec![r * t.cos(), r * t.sin()]
+        })
+        .collect();
+    let brute = BruteBackend::with_metric(pts.clone(), Cosine).unwrap();
+    let hnsw = HnswBackend::with_metric(pts.clone(), HnswParams::default(), Cosine).unwrap();
+
+    let mut hits = 0;
+    let mut total = 0;
+    for i in (0..300).step_by(10) {
+        let exact = brute.knn_indices_dist2(i, 5);
+        let approx = hnsw.knn_indices_dist2(i, 5);
+        assert_eq!(approx.len(), 5);
+        for &(j, d2) in &approx {
+            assert!((d2 - Cosine.dist2(&pts[i], &pts[j])).abs() < 1e-4);
+        }
+        for (a, e) in approx.iter().zip(exact.iter()) {
+            if (a.1 - e.1).abs() < 1e-4 {
+                hits += 1;
+            }
+        }
+        total += exact.len();
+    }
+    assert!(hits as f64 >= 0.9 * total as f64);
+}
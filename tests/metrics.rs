@@ -0,0 +1,140 @@
+use proptest::prelude::*;
+
+use tomato::backend::{BruteBackend, KdTreeBackend, PrecomputedBackend};
+use tomato::metrics::{
+    adjusted_rand_index, calinski_harabasz, davies_bouldin, fowlkes_mallows, normalized_mutual_info, silhouette,
+    v_measure, NoiseHandling,
+};
+use tomato::{TomatoError, NOISE};
+
+const EXCLUDE: NoiseHandling = NoiseHandling::Exclude;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-12
+}
+
+#[test]
+fn external_indices_match_reference_values() {
+    let truth = [0, 0, 1, 1];
+    let pred = [0, 0, 1, 2];
+
+    assert!(close(adjusted_rand_index(&truth, &pred, EXCLUDE).unwrap(), 4.0 / 7.0));
+    assert!(close(normalized_mutual_info(&truth, &pred, EXCLUDE).unwrap(), 0.8));
+    assert!(close(fowlkes_mallows(&truth, &pred, EXCLUDE).unwrap(), 0.5f64.sqrt()));
+
+    let v = v_measure(&truth, &pred, EXCLUDE).unwrap();
+    assert!(close(v.homogeneity, 1.0));
+    assert!(close(v.completeness, 2.0 / 3.0));
+    assert!(close(v.v_measure, 0.8));
+
+    // one cluster against all singletons
+    let one = [7, 7, 7, 7];
+    let all = [0, 1, 2, 3];
+    assert!(close(adjusted_rand_index(&one, &all, EXCLUDE).unwrap(), 0.0));
+    assert!(close(normalized_mutual_info(&one, &one, EXCLUDE).unwrap(), 1.0));
+    assert!(close(fowlkes_mallows(&one, &all, EXCLUDE).unwrap(), 0.0));
+
+    assert!(matches!(
+        adjusted_rand_index(&truth, &pred[..3], EXCLUDE),
+        Err(TomatoError::InvalidLabels(_))
+    ));
+}
+
+#[test]
+fn noise_handling_changes_what_is_compared() {
+    let truth = [0, 0, 0, 1, 1, 1];
+    let pred = [5, 5, NOISE, 9, 9, NOISE];
+
+    // dropping the noise leaves a perfect match
+    assert!(close(adjusted_rand_index(&truth, &pred, NoiseHandling::Exclude).unwrap(), 1.0));
+
+    // singletons split both classes, one noise cluster merges a point of each into a third
+    let singletons = adjusted_rand_index(&truth, &pred, NoiseHandling::Singletons).unwrap();
+    let cluster = adjusted_rand_index(&truth, &pred, NoiseHandling::Cluster).unwrap();
+    assert!(singletons < 1.0 && cluster < 1.0);
+    assert!(singletons > cluster);
+    assert!(close(
+        singletons,
+        adjusted_rand_index(&truth, &[5, 5, 100, 9, 9, 101], EXCLUDE).unwrap()
+    ));
+    assert!(close(cluster, adjusted_rand_index(&truth, &[5, 5, 100, 9, 9, 100], EXCLUDE).unwrap()));
+
+    // noise in the ground truth too
+    let truth = [0, NOISE, 0, 1, 1, 1];
+    assert!(close(normalized_mutual_info(&truth, &pred, NoiseHandling::Exclude).unwrap(), 1.0));
+}
+
+proptest! {
+    #[test]
+    fn external_indices_ignore_label_names(labels in prop::collection::vec(0usize..5, 2..40), shift in 1usize..100) {
+        let renamed: Vec<usize> = labels.iter().map(|&l| (l * 7 + shift) % 1000).collect();
+        prop_assert!(close(adjusted_rand_index(&labels, &renamed, EXCLUDE).unwrap(), 1.0));
+        prop_assert!(close(normalized_mutual_info(&labels, &renamed, EXCLUDE).unwrap(), 1.0));
+        prop_assert!(close(v_measure(&labels, &renamed, EXCLUDE).unwrap().v_measure, 1.0));
+        // with no two points sharing a label there are no pairs to recover
+        let distinct = labels.iter().collect::<std::collections::HashSet<_>>().len();
+        let fmi = fowlkes_mallows(&labels, &renamed, EXCLUDE).unwrap();
+        let expected = if distinct < labels.len() { 1.0 } else { 0.0 };
+        prop_assert!(close(fmi, expected));
+    }
+
+    #[test]
+    fn external_indices_are_symmetric(
+        a in prop::collection::vec(0usize..4, 10),
+        b in prop::collection::vec(0usize..4, 10),
+    ) {
+        prop_assert!(close(adjusted_rand_index(&a, &b, EXCLUDE).unwrap(), adjusted_rand_index(&b, &a, EXCLUDE).unwrap()));
+        prop_assert!(close(normalized_mutual_info(&a, &b, EXCLUDE).unwrap(), normalized_mutual_info(&b, &a, EXCLUDE).unwrap()));
+        prop_assert!(close(fowlkes_mallows(&a, &b, EXCLUDE).unwrap(), fowlkes_mallows(&b, &a, EXCLUDE).unwrap()));
+        let nmi = normalized_mutual_info(&a, &b, EXCLUDE).unwrap();
+        prop_assert!((0.0..=1.0).contains(&nmi));
+        prop_assert!(close(v_measure(&a, &b, EXCLUDE).unwrap().v_measure, nmi));
+    }
+}
+
+#[test]
+fn internal_indices_on_a_line() {
+    let points = vec![vec![0.0], vec![1.0], vec![10.0], vec![11.0]];
+    let labels = [3, 3, 8, 8];
+
+    let expected = 0.5 * (9.5 / 10.5 + 8.5 / 9.5);
+    let brute = BruteBackend::new(points.clone()).unwrap();
+    assert!(close(silhouette(&brute, &labels).unwrap(), expected));
+    let kd = KdTreeBackend::new(points.clone()).unwrap();
+    assert!(close(silhouette(&kd, &labels).unwrap(), expected));
+    let dense: Vec<Vec<f64>> = points
+        .iter()
+        .map(|p| points.iter().map(|q| (p[0] - q[0]).abs()).collect())
+        .collect();
+    let pre = PrecomputedBackend::from_dense(dense).unwrap();
+    assert!(close(silhouette(&pre, &labels).unwrap(), expected));
+
+    assert!(close(davies_bouldin(&points, &labels).unwrap(), 0.1));
+    assert!(close(calinski_harabasz(&points, &labels).unwrap(), 200.0));
+
+    // a far noise point is left out everywhere
+    let mut noisy = points.clone();
+    noisy.push(vec![-50.0]);
+    let noisy_labels = [3, 3, 8, 8, NOISE];
+    let brute = BruteBackend::new(noisy.clone()).unwrap();
+    assert!(close(silhouette(&brute, &noisy_labels).unwrap(), expected));
+    assert!(close(davies_bouldin(&noisy, &noisy_labels).unwrap(), 0.1));
+    assert!(close(calinski_harabasz(&noisy, &noisy_labels).unwrap(), 200.0));
+}
+
+#[test]
+fn internal_indices_need_two_to_n_minus_one_clusters() {
+    let points = vec![vec![0.0], vec![1.0], vec![10.0]];
+    let brute = BruteBackend::new(points.clone()).unwrap();
+
+    for labels in [[0, 0, 0], [0, 1, 2], [0, NOISE, NOISE]] {
+        assert!(matches!(silhouette(&brute, &labels), Err(TomatoError::InvalidLabels(_))));
+        assert!(matches!(davies_bouldin(&points, &labels), Err(TomatoError::InvalidLabels(_))));
+        assert!(matches!(calinski_harabasz(&points, &labels), Err(TomatoError::InvalidLabels(_))));
+    }
+    assert!(matches!(silhouette(&brute, &[0, 1]), Err(TomatoError::InvalidLabels(_))));
+
+    // a singleton cluster scores 0
+    let s = silhouette(&brute, &[0, 0, 1]).unwrap();
+    assert!(close(s, (0.9 + 8.0 / 9.0) / 3.0));
+}
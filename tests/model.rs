@@ -0,0 +1,96 @@
+use tomato::backend::BruteBackend;
+use tomato::pipeline::{run_pipeline, DensitySpec, GraphSpec, PipelineParams, TomatoModel};
+use tomato::tomato::{NoisePolicy, TomatoParams, NOISE};
+
+fn two_blobs() -> Vec<Vec<f64>> {
+    let offsets = [-1.2, -0.8, -0.5, -0.3, -0.15, -0.05, 0.0, 0.05, 0.15, 0.3, 0.5, 0.8, 1.2];
+    let mut pts = Vec::new();
+    for (i, &t) in offsets.iter().enumerate() {
+        pts.push(vec![t, 0.02 * (i % 3) as f64]);
+        pts.push(vec![10.0 + 1.3 * t, 0.02 * (i % 4) as f64]);
+    }
+    pts
+}
+
+fn params() -> PipelineParams {
+    PipelineParams {
+        graph: GraphSpec::RipsBrute { radius2: 0.3 },
+        density: DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.02 },
+        tomato: TomatoParams::new(0.5),
+    }
+}
+
+fn assert_matches_refit(params: PipelineParams, queries: &[Vec<f64>]) -> Vec<usize> {
+    let pts = two_blobs();
+    let n = pts.len();
+    let model = TomatoModel::fit(BruteBackend::new(pts.clone()).unwrap(), params.clone()).unwrap();
+    let mut labels = Vec::new();
+    for q in queries {
+        let pred = model.predict(q).unwrap();
+        let mut all = pts.clone();
+        all.push(q.clone());
+        let refit = run_pipeline(&BruteBackend::new(all).unwrap(), params.clone()).unwrap();
+        let mut modes = refit.tomato.modes.clone();
+        if refit.tomato.cluster_of[n] == n {
+            assert_eq!(pred.label, NOISE);
+            modes.retain(|&m| m != n);
+        } else {
+            assert_eq!(refit.tomato.cluster_of[n], pred.label);
+        }
+        let mut fitted = model.result().modes.clone();
+        modes.sort_unstable();
+        fitted.sort_unstable();
+        assert_eq!(modes, fitted);
+        assert!((refit.density[n] - pred.density).abs() < 1e-12);
+        labels.push(pred.label);
+    }
+    labels
+}
+
+#[test]
+fn predict_matches_refit() {
+    let model = TomatoModel::fit(BruteBackend::new(two_blobs()).unwrap(), params()).unwrap();
+    assert_eq!(model.result().modes.len(), 2);
+    let labels = assert_matches_refit(params(), &[vec![0.95, 0.01], vec![8.77, 0.03]]);
+    assert_eq!(labels, [model.labels()[0], model.labels()[1]]);
+}
+
+#[test]
+fn predict_matches_refit_at_tau_zero() {
+    let p = PipelineParams { tomato: TomatoParams::new(0.0), ..params() };
+    let labels = assert_matches_refit(p, &[vec![0.95, 0.01], vec![8.77, 0.03], vec![0.02, 0.01]]);
+    assert!(labels.iter().all(|&l| l == NOISE));
+}
+
+#[test]
+fn predict_matches_refit_on_knn_graphs() {
+    let queries = [vec![0.95, 0.01], vec![8.77, 0.03], vec![-0.65, 0.0]];
+    for graph in [
+        GraphSpec::Knn { k: 4, symmetrize: true },
+        GraphSpec::RipsFromKnnApprox { k: 4, radius2: 0.3, symmetrize: true },
+    ] {
+        let p = PipelineParams { graph, ..params() };
+        let labels = assert_matches_refit(p, &queries);
+        assert!(labels.iter().all(|&l| l != NOISE));
+    }
+}
+
+#[test]
+fn predict_matches_refit_with_noise_policy() {
+    let noise = NoisePolicy { min_density: Some(0.3), min_cluster_size: Some(3), min_prominence: None };
+    let p = PipelineParams { tomato: TomatoParams::new(0.5).with_noise(noise), ..params() };
+    let labels = assert_matches_refit(p, &[vec![-0.65, 0.0], vec![1.45, 0.0], vec![8.77, 0.03]]);
+    assert_ne!(labels[0], NOISE);
+    assert_eq!(labels[1], NOISE);
+    assert_ne!(labels[2], NOISE);
+}
+
+#[test]
+fn predict_far_point_is_noise() {
+    let model = TomatoModel::fit(BruteBackend::new(two_blobs()).unwrap(), params()).unwrap();
+    let top = model.predict(&[0.01, 0.02]).unwrap();
+    assert_eq!(top.label, model.labels()[0]);
+    let pred = model.predict(&[5.0, 5.0]).unwrap();
+    assert_eq!(pred.label, NOISE);
+    assert!(model.predict(&[1.0]).is_err());
+}
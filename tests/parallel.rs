@@ -0,0 +1,147 @@
+// The plain entry points must match the per point loops; with the parallel feature, the `_par`
+// entry points must match the plain ones bit for bit.
+
+use tomato::backend::{AnnBackend, BruteBackend, HnswBackend, HnswParams, KdTreeBackend};
+use tomato::pipeline::{build_graph, estimate_density, estimate_density_at, DensitySpec, GraphSpec};
+use tomato::Graph;
+
+fn points() -> Vec<Vec<f64>> {
+    (0..1500)
+        .map(|i| {
+            let t = i as f64;
+            vec![(t * 0.013).sin() * 5.0 + (t * 0.7).cos(), (t * 0.021).cos() * 3.0, (t * 0.37).sin()]
+        })
+        .collect()
+}
+
+fn sequential_knn<B: AnnBackend>(backend: &B, k: usize) -> Vec<Vec<(usize, f64)>> {
+    let mut all = Vec::new();
+    for i in 0..backend.len() {
+        all.push(backend.knn_indices_dist2(i, k));
+    }
+    all
+}
+
+#[test]
+fn knn_and_density_match_sequential_loops() {
+    let pts = points();
+    let kd = KdTreeBackend::new(pts.clone()).unwrap();
+    assert_eq!(kd.knn_all_indices_dist2(12), sequential_knn(&kd, 12));
+
+    let bandwidth2: f64 = 0.4;
+    let expected: Vec<f64> = sequential_knn(&kd, 12)
+        .iter()
+        .map(|row| {
+            let mut s = 0.0;
+            for &(_j, d2) in row {
+                s += (-d2 * (1.0 / (2.0 * bandwidth2))).exp();
+            }
+            s
+        })
+        .collect();
+    let density = estimate_density(&kd, DensitySpec::KdeGaussianKnn { k: 12, bandwidth2 }).unwrap();
+    assert_eq!(density, expected);
+
+    let queries: Vec<Vec<f64>> = pts.iter().step_by(5).map(|p| vec![p[0] + 0.01, p[1], p[2] - 0.02]).collect();
+    let spec = DensitySpec::KnnLog { k: 7, eps: 1e-9 };
+    let batch = estimate_density_at(&kd, &spec, &queries).unwrap();
+    for (q, &f) in queries.iter().zip(batch.iter()) {
+        assert_eq!(tomato::pipeline::density_at_point(&kd, &spec, q).unwrap(), f);
+    }
+    let mut bad = queries.clone();
+    bad[40] = vec![0.0];
+    bad[90] = vec![f64::NAN, 0.0, 0.0];
+    assert!(matches!(
+        estimate_density_at(&kd, &spec, &bad),
+        Err(tomato::TomatoError::QueryDimensionMismatch { .. })
+    ));
+}
+
+#[test]
+fn rips_graph_matches_sequential_loop() {
+    let pts = points();
+    let brute = BruteBackend::new(pts).unwrap();
+    let radius2 = 0.05;
+    let mut adj = Vec::new();
+    for i in 0..brute.len() {
+        adj.push(brute.range_dist2(i, radius2).into_iter().map(|x| x.0).collect());
+    }
+    let expected = Graph::new(Graph::symmetrize_and_dedup(adj)).unwrap();
+    let graph = build_graph(&brute, GraphSpec::RipsBrute { radius2 }).unwrap();
+    for i in 0..graph.n() {
+        assert_eq!(graph.neighbors(i), expected.neighbors(i));
+    }
+}
+
+#[test]
+fn hnsw_knn_matches_sequential_loop() {
+    let hnsw = HnswBackend::new(points(), HnswParams::default()).unwrap();
+    assert_eq!(hnsw.knn_all_indices_dist2(10), sequential_knn(&hnsw, 10));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_entry_points_match_sequential_ones() {
+    use tomato::pipeline::{
+        build_graph_par, estimate_density_at_par, estimate_density_par, run_pipeline, run_pipeline_par, smooth_density,
+        smooth_density_par, KnnTable, PipelineParams, Smoothing, SmoothingMethod,
+    };
+    use tomato::TomatoParams;
+
+    let pts = points();
+    let kd = KdTreeBackend::new(pts.clone()).unwrap();
+    assert_eq!(KnnTable::build_par(&kd, 12), KnnTable::build(&kd, 12));
+
+    for spec in [
+        GraphSpec::Knn { k: 8, symmetrize: true },
+        GraphSpec::RipsBrute { radius2: 0.05 },
+        GraphSpec::RipsFromKnnApprox {
+            k: 12,
+            radius2: 0.05,
+            symmetrize: false,
+        },
+    ] {
+        let a = build_graph(&kd, spec.clone()).unwrap();
+        let b = build_graph_par(&kd, spec).unwrap();
+        for i in 0..a.n() {
+            assert_eq!(a.neighbors(i), b.neighbors(i));
+        }
+    }
+
+    for spec in [
+        DensitySpec::KdeGaussianKnn { k: 12, bandwidth2: 0.4 },
+        DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.4 },
+        DensitySpec::KnnLog { k: 7, eps: 1e-9 },
+    ] {
+        assert_eq!(estimate_density(&kd, spec.clone()).unwrap(), estimate_density_par(&kd, spec.clone()).unwrap());
+        let queries: Vec<Vec<f64>> = pts.iter().step_by(25).map(|p| vec![p[0] + 0.01, p[1], p[2]]).collect();
+        assert_eq!(
+            estimate_density_at(&kd, &spec, &queries).unwrap(),
+            estimate_density_at_par(&kd, &spec, &queries).unwrap()
+        );
+    }
+
+    let params = PipelineParams {
+        graph: GraphSpec::RipsFromKnnApprox {
+            k: 12,
+            radius2: 0.05,
+            symmetrize: true,
+        },
+        density: DensitySpec::KdeGaussianKnn { k: 12, bandwidth2: 0.4 },
+        tomato: TomatoParams::new(0.5),
+    };
+    let a = run_pipeline(&kd, params.clone()).unwrap();
+    let b = run_pipeline_par(&kd, params.clone()).unwrap();
+    assert_eq!(a.density, b.density);
+    assert_eq!(a.tomato.cluster_of, b.tomato.cluster_of);
+
+    let smoothing = Smoothing::new(SmoothingMethod::Median, 2);
+    assert_eq!(
+        smooth_density(&a.graph, &a.density, &smoothing).unwrap(),
+        smooth_density_par(&a.graph, &a.density, &smoothing).unwrap()
+    );
+    let a = run_pipeline(&kd, params.clone().with_smoothing(smoothing)).unwrap();
+    let b = run_pipeline_par(&kd, params.with_smoothing(smoothing)).unwrap();
+    assert_eq!(a.density, b.density);
+    assert_eq!(a.tomato.cluster_of, b.tomato.cluster_of);
+}
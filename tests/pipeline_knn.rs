@@ -1,5 +1,5 @@
-use tomato::backend::{BruteBackend, HnswBackend, HnswParams};
-use tomato::pipeline::{build_graph, estimate_density, GraphSpec, DensitySpec};
+use tomato::backend::{AnnBackend, BruteBackend, HnswBackend, HnswParams};
+use tomato::pipeline::{build_graph, estimate_density, estimate_density_at, GraphSpec, DensitySpec};
 use tomato::tomato::{tomato_cluster, TomatoParams};
 use tomato::stats::zscore_in_place;
 
@@ -41,6 +41,79 @@ fn pipeline_runs_brute() {
     let g = build_graph(&brute, GraphSpec::Knn { k: 2, symmetrize: true }).unwrap();
     let f = estimate_density(&brute, DensitySpec::KnnLog { k: 2, eps: 1e-12 }).unwrap();
 
-    let res = tomato_cluster(&g, &f, TomatoParams { tau: 0.0 }).unwrap();
+    let res = tomato_cluster(&g, &f, TomatoParams::new(0.0)).unwrap();
     assert_eq!(res.cluster_of.len(), 4);
-}
\ No newline at end of file
+}
+
+#[test]
+fn point_queries_match_index_queries() {
+    let pts: Vec<Vec<f64>> = (0..40)
+        .map(|i| vec![(i as f64 * 0.37).sin(), (i as f64 * 0.11).cos()])
+        .collect();
+    let brute = BruteBackend::new(pts.clone()).unwrap();
+
+    for i in [0usize, 7, 39] {
+        let by_index = brute.knn_indices_dist2(i, 5);
+        let by_point = brute.knn_point_dist2(&pts[i], 6).unwrap();
+        assert_eq!(by_point[0], (i, 0.0));
+        assert_eq!(&by_point[1..], &by_index[..]);
+    }
+
+    let grid = vec![vec![0.0, 0.0], vec![0.5, -0.5]];
+    let all = brute.knn_points_dist2(&grid, 3).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[1], brute.knn_point_dist2(&grid[1], 3).unwrap());
+    assert!(brute.knn_point_dist2(&[0.0], 3).is_err());
+    assert!(brute.knn_point_dist2(&[0.0, f64::NAN], 3).is_err());
+
+    let spec = DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.3 };
+    let stored = estimate_density(&brute, spec.clone()).unwrap();
+    let at = estimate_density_at(&brute, &spec, &pts[..3]).unwrap();
+    for i in 0..3 {
+        // a stored point queried from outside also sees itself at distance zero
+        assert!((at[i] - stored[i] - 1.0).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn hnsw_point_query_on_line() {
+    let hnsw = HnswBackend::new(line_points(200), HnswParams {
+        ef_search: 256,
+        ef_construction: 256,
+        max_nb_connection: 32,
+        max_layer: 16,
+        use_parallel_insert: false,
+    }).unwrap();
+
+    let ans = hnsw.knn_point_dist2(&[100.4], 2).unwrap();
+    let ids: Vec<usize> = ans.iter().map(|x| x.0).collect();
+    assert_eq!(ids, vec![100, 101]);
+    assert!((ans[0].1 - 0.16).abs() < 1e-4);
+}
+
+#[test]
+fn range_queries_match_filtered_knn() {
+    let pts: Vec<Vec<f64>> = (0..60)
+        .map(|i| vec![(i as f64 * 0.37).sin(), (i as f64 * 0.11).cos()])
+        .collect();
+    let brute = BruteBackend::new(pts.clone()).unwrap();
+    let radius2 = 0.05;
+
+    for i in 0..pts.len() {
+        let mut range = brute.range_dist2(i, radius2);
+        range.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        let mut knn = brute.knn_indices_dist2(i, pts.len());
+        knn.retain(|&(_j, d2)| d2 <= radius2);
+        assert_eq!(range, knn);
+
+        let around = brute.range_point_dist2(&pts[i], radius2).unwrap();
+        assert_eq!(around.len(), knn.len() + 1);
+    }
+
+    let g = build_graph(&brute, GraphSpec::RipsBrute { radius2 }).unwrap();
+    for i in 0..pts.len() {
+        let mut expected: Vec<usize> = brute.range_dist2(i, radius2).into_iter().map(|x| x.0).collect();
+        expected.sort_unstable();
+        assert_eq!(g.neighbors(i), &expected[..]);
+    }
+}
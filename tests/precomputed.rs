@@ -0,0 +1,106 @@
+use tomato::backend::{AnnBackend, BruteBackend, PrecomputedBackend};
+use tomato::pipeline::{build_graph, estimate_density, run_pipeline, DensitySpec, GraphSpec, PipelineParams};
+use tomato::{TomatoError, TomatoParams};
+
+fn points() -> Vec<Vec<f64>> {
+    (0..120)
+        .map(|i| {
+            let c = if i % 2 == 0 { 0.0 } else { 6.0 };
+            vec![c + (i as f64 * 0.37).sin(), (i as f64 * 0.73).cos()]
+        })
+        .collect()
+}
+
+fn dense(pts: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    pts.iter()
+        .map(|a| {
+            pts.iter()
+                .map(|b| ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn precomputed_matches_brute_on_euclidean_distances() {
+    let pts = points();
+    let brute = BruteBackend::new(pts.clone()).unwrap();
+    let pre = PrecomputedBackend::from_dense(dense(&pts)).unwrap();
+    assert_eq!(pre.len(), pts.len());
+    assert_eq!(pre.dim(), 0);
+
+    for i in 0..pts.len() {
+        let a = pre.knn_indices_dist2(i, 6);
+        let b = brute.knn_indices_dist2(i, 6);
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x.1 - y.1).abs() < 1e-9);
+        }
+    }
+
+    let spec = GraphSpec::RipsBrute { radius2: 0.5 };
+    let gp = build_graph(&pre, spec.clone()).unwrap();
+    let gb = build_graph(&brute, spec).unwrap();
+    for i in 0..gp.n() {
+        assert_eq!(gp.neighbors(i), gb.neighbors(i));
+    }
+
+    let spec = DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.5 };
+    let dp = estimate_density(&pre, spec.clone()).unwrap();
+    let db = estimate_density(&brute, spec).unwrap();
+    for i in 0..dp.len() {
+        assert!((dp[i] - db[i]).abs() < 1e-9);
+    }
+
+    let params = PipelineParams {
+        graph: GraphSpec::Knn { k: 8, symmetrize: true },
+        density: DensitySpec::KdeGaussianKnn { k: 8, bandwidth2: 0.5 },
+        tomato: TomatoParams::new(0.5),
+    };
+    let out = run_pipeline(&pre, params.clone()).unwrap();
+    let expected = run_pipeline(&brute, params).unwrap();
+    assert_eq!(out.tomato.modes, expected.tomato.modes);
+    assert_eq!(out.tomato.cluster_of, expected.tomato.cluster_of);
+}
+
+#[test]
+fn condensed_matches_dense() {
+    let pts = points();
+    let d = dense(&pts);
+    let n = pts.len();
+    let mut condensed = Vec::new();
+    for (i, row) in d.iter().enumerate() {
+        condensed.extend_from_slice(&row[i + 1..]);
+    }
+    let a = PrecomputedBackend::from_dense(d).unwrap();
+    let b = PrecomputedBackend::from_condensed(n, condensed).unwrap();
+    for i in 0..n {
+        for j in 0..n {
+            assert_eq!(a.dist2(i, j), b.dist2(i, j));
+        }
+        assert_eq!(a.knn_indices_dist2(i, 5), b.knn_indices_dist2(i, 5));
+    }
+
+    assert!(PrecomputedBackend::from_condensed(4, vec![1.0; 5]).is_err());
+    assert!(PrecomputedBackend::from_condensed(3, vec![1.0, -1.0, 1.0]).is_err());
+    assert!(PrecomputedBackend::from_dense(vec![vec![0.0, 1.0], vec![2.0, 0.0]]).is_err());
+    assert!(PrecomputedBackend::from_dense(vec![vec![0.0, 1.0]]).is_err());
+}
+
+#[test]
+fn knn_log_needs_an_intrinsic_dimension() {
+    let pts = points();
+    let spec = DensitySpec::KnnLog { k: 5, eps: 1e-12 };
+
+    let pre = PrecomputedBackend::from_dense(dense(&pts)).unwrap();
+    assert!(matches!(estimate_density(&pre, spec.clone()), Err(TomatoError::InvalidGraph(_))));
+
+    let pre = pre.with_intrinsic_dim(2);
+    let dp = estimate_density(&pre, spec.clone()).unwrap();
+    let db = estimate_density(&BruteBackend::new(pts).unwrap(), spec).unwrap();
+    for i in 0..dp.len() {
+        assert!((dp[i] - db[i]).abs() < 1e-9);
+    }
+
+    assert!(matches!(pre.knn_point_dist2(&[0.0, 0.0], 3), Err(TomatoError::UnsupportedQuery(_))));
+    assert!(matches!(pre.range_point_dist2(&[0.0, 0.0], 1.0), Err(TomatoError::UnsupportedQuery(_))));
+}
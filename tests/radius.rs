@@ -0,0 +1,87 @@
+use tomato::backend::BruteBackend;
+use tomato::pipeline::{
+    bandwidth_radius2, component_stats, connecting_radius2, knn_quantile_radius2, rips_component_stats,
+    ComponentStats, RadiusRule,
+};
+use tomato::{Graph, TomatoError};
+
+// Two groups of three and an outlier on a line.
+fn backend() -> BruteBackend {
+    let xs = [0.0, 1.0, 2.0, 10.0, 11.0, 12.0, 30.0];
+    BruteBackend::new(xs.iter().map(|&x| vec![x]).collect()).unwrap()
+}
+
+#[test]
+fn component_stats_count_components_and_degrees() {
+    let g = Graph::new(vec![vec![1], vec![0, 2], vec![1], vec![], vec![5], vec![4]]).unwrap();
+    assert_eq!(
+        component_stats(&g),
+        ComponentStats {
+            n_components: 3,
+            largest: 3,
+            largest_fraction: 0.5,
+            n_singletons: 1,
+            mean_degree: 1.0,
+        }
+    );
+
+    let empty = component_stats(&Graph::new(Vec::new()).unwrap());
+    assert_eq!(empty.n_components, 0);
+    assert_eq!(empty.largest_fraction, 0.0);
+}
+
+#[test]
+fn knn_quantile_radius_and_its_components() {
+    let b = backend();
+
+    let median = knn_quantile_radius2(&b, 1, 0.5).unwrap();
+    assert_eq!(median.radius2, 1.0);
+    assert_eq!(median.rule, RadiusRule::KnnQuantile { k: 1, q: 0.5 });
+    assert_eq!(median.stats.n_components, 3);
+    assert_eq!(median.stats.largest, 3);
+    assert_eq!(median.stats.n_singletons, 1);
+    assert!((median.stats.mean_degree - 8.0 / 7.0).abs() < 1e-12);
+
+    // the largest kth distance leaves no point alone
+    let all = knn_quantile_radius2(&b, 1, 1.0).unwrap();
+    assert_eq!(all.radius2, 324.0);
+    assert_eq!(all.stats.n_singletons, 0);
+    assert_eq!(all.stats.n_components, 1);
+
+    assert!(matches!(knn_quantile_radius2(&b, 0, 0.5), Err(TomatoError::Radius(_))));
+    assert!(matches!(knn_quantile_radius2(&b, 1, 1.5), Err(TomatoError::Radius(_))));
+}
+
+#[test]
+fn connecting_radius_is_the_single_linkage_height() {
+    let b = backend();
+
+    let six = connecting_radius2(&b, 6.0 / 7.0, 6).unwrap();
+    assert_eq!(six.radius2, 64.0);
+    assert_eq!(six.stats.largest, 6);
+    assert_eq!(six.stats, rips_component_stats(&b, 64.0).unwrap());
+
+    let half = connecting_radius2(&b, 0.4, 6).unwrap();
+    assert_eq!(half.radius2, 1.0);
+    assert!(half.stats.largest_fraction >= 0.4);
+
+    assert_eq!(connecting_radius2(&b, 0.1, 1).unwrap().radius2, 0.0);
+    assert_eq!(connecting_radius2(&b, 1.0, 6).unwrap().radius2, 324.0);
+
+    // with one neighbor per point the two groups never meet
+    let err = connecting_radius2(&b, 1.0, 1).unwrap_err();
+    assert!(matches!(err, TomatoError::Radius(ref m) if m.contains("raise k")));
+    assert!(connecting_radius2(&b, 0.0, 3).is_err());
+}
+
+#[test]
+fn bandwidth_radius_scales_sigma() {
+    let b = backend();
+    let r = bandwidth_radius2(&b, 0.25, 2.0).unwrap();
+    assert_eq!(r.radius2, 1.0);
+    assert_eq!(r.rule, RadiusRule::Bandwidth { bandwidth2: 0.25, scale: 2.0 });
+    assert_eq!(r.stats, knn_quantile_radius2(&b, 1, 0.5).unwrap().stats);
+
+    assert!(bandwidth_radius2(&b, 0.0, 2.0).is_err());
+    assert!(bandwidth_radius2(&b, 1.0, f64::INFINITY).is_err());
+}
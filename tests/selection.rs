@@ -0,0 +1,77 @@
+use tomato::graph::Graph;
+use tomato::persistence::PersistencePair;
+use tomato::selection::{select_tau, TauCriterion};
+use tomato::tomato::{tomato_cluster, TomatoParams};
+
+fn pair(mode: usize, birth: f64, death: Option<f64>) -> PersistencePair {
+    PersistencePair {
+        mode,
+        birth,
+        death,
+        saddle: death.map(|_| 100 + mode),
+    }
+}
+
+fn diagram() -> Vec<PersistencePair> {
+    vec![
+        pair(0, 10.0, None),
+        pair(1, 9.0, Some(1.0)),
+        pair(2, 8.0, Some(1.5)),
+        pair(3, 2.0, Some(1.5)),
+        pair(4, 1.8, Some(1.6)),
+    ]
+}
+
+#[test]
+fn largest_gap_separates_prominent_modes() {
+    let sel = select_tau(&diagram(), TauCriterion::LargestGap).unwrap();
+    assert_eq!(sel.n_clusters, 3);
+    assert_eq!(sel.merged, 0.5);
+    assert_eq!(sel.kept, 6.5);
+    assert!(sel.tau > sel.merged && sel.tau < sel.kept);
+}
+
+#[test]
+fn largest_gap_can_keep_only_the_essential_classes() {
+    // the span is 9, far above the two small prominences
+    let diag = vec![pair(0, 10.0, None), pair(1, 1.5, Some(1.0)), pair(2, 1.3, Some(1.0))];
+    let sel = select_tau(&diag, TauCriterion::LargestGap).unwrap();
+    assert_eq!(sel.n_clusters, 1);
+    assert_eq!(sel.merged, 0.5);
+    assert_eq!(sel.kept, f64::INFINITY);
+    assert!(sel.tau > 0.5);
+
+    let sel = select_tau(&[pair(0, 10.0, None)], TauCriterion::LargestGap).unwrap();
+    assert_eq!(sel.n_clusters, 1);
+}
+
+#[test]
+fn n_clusters_and_fraction() {
+    let sel = select_tau(&diagram(), TauCriterion::NClusters(4)).unwrap();
+    assert_eq!(sel.n_clusters, 4);
+    assert!(sel.tau > 0.2 && sel.tau <= 0.5);
+
+    let sel = select_tau(&diagram(), TauCriterion::NClusters(1)).unwrap();
+    assert_eq!(sel.n_clusters, 1);
+    assert!(sel.tau > 8.0);
+
+    assert!(select_tau(&diagram(), TauCriterion::NClusters(0)).is_err());
+    assert!(select_tau(&diagram(), TauCriterion::NClusters(6)).is_err());
+
+    let sel = select_tau(&diagram(), TauCriterion::ProminenceFraction(0.5)).unwrap();
+    assert_eq!(sel.tau, 4.5);
+    assert_eq!(sel.n_clusters, 3);
+}
+
+#[test]
+fn selection_reproduces_cluster_count() {
+    let g = Graph::new(vec![vec![3], vec![3], vec![3], vec![1, 0, 2]]).unwrap();
+    let f = vec![4.0, 5.0, 10.0, 0.0];
+    let diag = tomato_cluster(&g, &f, TomatoParams::new(1e100)).unwrap().diagram;
+
+    for k in 1..=3 {
+        let sel = select_tau(&diag, TauCriterion::NClusters(k)).unwrap();
+        let res = tomato_cluster(&g, &f, TomatoParams::new(sel.tau)).unwrap();
+        assert_eq!(res.modes.len(), k);
+    }
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "serde")]
+
+use tomato::backend::BruteBackend;
+use tomato::pipeline::{
+    run_pipeline, DensitySpec, GraphSpec, KnnTable, PipelineParams, PipelineResult, Smoothing, SmoothingMethod,
+};
+use tomato::selection::{select_tau, TauCriterion, TauSelection};
+use tomato::{Graph, HnswParams, NoisePolicy, TomatoParams, TomatoResult, NOISE};
+
+fn params() -> PipelineParams {
+    PipelineParams {
+        graph: GraphSpec::Knn { k: 8, symmetrize: true },
+        density: DensitySpec::KdeGaussianKnn { k: 12, bandwidth2: 0.25 },
+        tomato: TomatoParams::new(0.5).with_noise(NoisePolicy {
+            min_density: None,
+            min_cluster_size: Some(3),
+            min_prominence: None,
+        }),
+    }
+}
+
+fn points() -> Vec<Vec<f64>> {
+    (0..60)
+        .map(|i| {
+            let c = if i % 2 == 0 { 0.0 } else { 5.0 };
+            vec![c + (i as f64 * 0.9).sin(), c + (i as f64 * 1.7).cos()]
+        })
+        .collect()
+}
+
+#[test]
+fn specs_are_externally_tagged() {
+    let json = serde_json::to_value(params()).unwrap();
+    assert_eq!(json["graph"], serde_json::json!({"knn": {"k": 8, "symmetrize": true}}));
+    assert_eq!(json["density"], serde_json::json!({"kde_gaussian_knn": {"k": 12, "bandwidth2": 0.25}}));
+    assert_eq!(json["tomato"]["tau"], serde_json::json!(0.5));
+    assert_eq!(json["tomato"]["noise"]["min_cluster_size"], serde_json::json!(3));
+
+    assert_eq!(
+        serde_json::to_value(TauCriterion::LargestGap).unwrap(),
+        serde_json::json!("largest_gap")
+    );
+    assert_eq!(
+        serde_json::to_value(TauCriterion::NClusters(3)).unwrap(),
+        serde_json::json!({"n_clusters": 3})
+    );
+}
+
+#[test]
+fn params_read_from_json_and_toml_with_defaults() {
+    let json = r#"{
+        "graph": {"rips_brute": {"radius2": 1.5}},
+        "density": {"knn_log": {"k": 10, "eps": 1e-12}},
+        "tomato": {"tau": "inf"}
+    }"#;
+    let p: PipelineParams = serde_json::from_str(json).unwrap();
+    assert!(matches!(p.graph, GraphSpec::RipsBrute { radius2 } if radius2 == 1.5));
+    assert!(matches!(p.density, DensitySpec::KnnLog { k: 10, .. }));
+    let dtm: DensitySpec = serde_json::from_str(r#"{"dtm": {"k": 8, "eps": 0.0}}"#).unwrap();
+    assert!(matches!(dtm, DensitySpec::Dtm { k: 8, p, .. } if p == 2.0));
+    assert_eq!(p.tomato.tau, f64::INFINITY);
+    assert!(p.tomato.noise.is_none());
+    assert!(p.tomato.smoothing.is_none());
+
+    let text = r#"
+        [graph.knn]
+        k = 15
+        symmetrize = false
+
+        [density.kde_gaussian_full_brute]
+        bandwidth2 = 0.5
+
+        [tomato]
+        tau = 0.25
+
+        [tomato.smoothing]
+        method = { weighted_mean = { self_weight = 2.0 } }
+        iterations = 3
+
+        [tomato.noise]
+        min_density = -3.0
+    "#;
+    let p: PipelineParams = toml::from_str(text).unwrap();
+    assert!(matches!(p.graph, GraphSpec::Knn { k: 15, symmetrize: false }));
+    assert_eq!(p.tomato.noise.min_density, Some(-3.0));
+    assert_eq!(
+        p.tomato.smoothing,
+        Some(Smoothing::new(SmoothingMethod::WeightedMean { self_weight: 2.0 }, 3))
+    );
+    assert_eq!(toml::from_str::<PipelineParams>(&toml::to_string(&p).unwrap()).unwrap().tomato.tau, 0.25);
+
+    let h: HnswParams = serde_json::from_str(r#"{"ef_search": 128}"#).unwrap();
+    assert_eq!(h.ef_search, 128);
+    assert_eq!(h.max_nb_connection, HnswParams::default().max_nb_connection);
+
+    assert!(serde_json::from_str::<PipelineParams>(r#"{"graph": {"knn": {"k": 3}}}"#).is_err());
+    assert!(serde_json::from_str::<TomatoParams>(r#"{"tau": "big"}"#).is_err());
+}
+
+#[test]
+fn pipeline_result_round_trips_with_noise_as_null() {
+    let backend = BruteBackend::new(points()).unwrap();
+    let res = run_pipeline(&backend, params()).unwrap();
+    assert!(res.tomato.n_noise() < res.tomato.cluster_of.len());
+
+    let mut tomato = res.tomato.clone();
+    tomato.cluster_of[0] = NOISE;
+    let json = serde_json::to_value(&tomato).unwrap();
+    assert!(json["cluster_of"][0].is_null());
+    let back: TomatoResult = serde_json::from_value(json).unwrap();
+    assert_eq!(back.cluster_of, tomato.cluster_of);
+    assert_eq!(back.diagram, tomato.diagram);
+
+    let text = serde_json::to_string(&res).unwrap();
+    let back: PipelineResult = serde_json::from_str(&text).unwrap();
+    assert_eq!(back.density, res.density);
+    assert_eq!(back.tomato.modes, res.tomato.modes);
+    for v in 0..res.graph.n() {
+        assert_eq!(back.graph.neighbors(v), res.graph.neighbors(v));
+    }
+
+    let sel = select_tau(&res.tomato.diagram, TauCriterion::LargestGap).unwrap();
+    let back: TauSelection = serde_json::from_str(&serde_json::to_string(&sel).unwrap()).unwrap();
+    assert_eq!(back, sel);
+}
+
+#[test]
+fn graph_and_knn_table_are_checked_when_read() {
+    assert!(serde_json::from_str::<Graph>("[[1], [0]]").is_ok());
+    assert!(serde_json::from_str::<Graph>("[[2], [0]]").is_err());
+
+    let backend = BruteBackend::new(points()).unwrap();
+    let knn = KnnTable::build(&backend, 4);
+    let back: KnnTable = serde_json::from_str(&serde_json::to_string(&knn).unwrap()).unwrap();
+    assert_eq!(back, knn);
+
+    let bad = r#"{"k": 1, "rows": [[[1, 0.5]], [[1, 0.5]]]}"#;
+    assert!(serde_json::from_str::<KnnTable>(bad).is_err());
+}
@@ -0,0 +1,119 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tomato::backend::BruteBackend;
+use tomato::pipeline::{
+    density_at_point, estimate_density, run_pipeline, smooth_density, sweep, DensitySpec, GraphSpec, PipelineParams,
+    Smoothing, SmoothingMethod, SweepGrid, SweepTau, TomatoModel,
+};
+use tomato::{tomato_cluster, Graph, TomatoError, TomatoParams};
+
+// A path 0 - 1 - 2 and an isolated vertex 3.
+fn path() -> Graph {
+    Graph::new(vec![vec![1], vec![0, 2], vec![1], vec![]]).unwrap()
+}
+
+fn smooth(method: SmoothingMethod, iterations: usize) -> Vec<f64> {
+    smooth_density(&path(), &[0.0, 3.0, 0.0, 5.0], &Smoothing::new(method, iterations)).unwrap()
+}
+
+// Two blobs with jittered points, whose raw kNN density has many small peaks.
+fn blobs() -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(11);
+    (0..300)
+        .map(|i| {
+            let c = if i % 2 == 0 { 0.0 } else { 5.0 };
+            vec![c + rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0)]
+        })
+        .collect()
+}
+
+fn params(smoothing: Option<Smoothing>) -> PipelineParams {
+    let p = PipelineParams::new(
+        GraphSpec::Knn { k: 8, symmetrize: true },
+        DensitySpec::KnnLog { k: 5, eps: 1e-12 },
+        TomatoParams::new(1e-9),
+    );
+    match smoothing {
+        Some(s) => p.with_smoothing(s),
+        None => p,
+    }
+}
+
+#[test]
+fn each_method_combines_a_vertex_with_its_neighbors() {
+    assert_eq!(smooth(SmoothingMethod::Mean, 1), vec![1.5, 1.0, 1.5, 5.0]);
+    assert_eq!(smooth(SmoothingMethod::Median, 1), vec![1.5, 0.0, 1.5, 5.0]);
+    assert_eq!(
+        smooth(SmoothingMethod::WeightedMean { self_weight: 2.0 }, 1),
+        vec![1.0, 1.5, 1.0, 5.0]
+    );
+    assert_eq!(smooth(SmoothingMethod::WeightedMean { self_weight: 0.0 }, 1), vec![3.0, 0.0, 3.0, 5.0]);
+
+    // iterations read the previous round, and zero leaves the density alone
+    assert_eq!(smooth(SmoothingMethod::Mean, 2), vec![1.25, 4.0 / 3.0, 1.25, 5.0]);
+    assert_eq!(smooth(SmoothingMethod::Median, 0), vec![0.0, 3.0, 0.0, 5.0]);
+}
+
+#[test]
+fn smoothing_rejects_bad_input() {
+    let mean = Smoothing::new(SmoothingMethod::Mean, 1);
+    assert!(matches!(
+        smooth_density(&path(), &[1.0, 2.0], &mean),
+        Err(TomatoError::DensityLengthMismatch)
+    ));
+    for w in [-1.0, f64::NAN, f64::INFINITY] {
+        let bad = Smoothing::new(SmoothingMethod::WeightedMean { self_weight: w }, 1);
+        assert!(matches!(
+            smooth_density(&path(), &[1.0; 4], &bad),
+            Err(TomatoError::InvalidSmoothing(_))
+        ));
+    }
+}
+
+#[test]
+fn pipeline_smooths_between_density_and_clustering() {
+    let b = BruteBackend::new(blobs()).unwrap();
+    let raw = run_pipeline(&b, params(None)).unwrap();
+    assert_eq!(raw.density, estimate_density(&b, params(None).density).unwrap());
+
+    for method in [SmoothingMethod::Mean, SmoothingMethod::Median] {
+        let smoothing = Smoothing::new(method, 3);
+        let out = run_pipeline(&b, params(Some(smoothing))).unwrap();
+        assert_eq!(out.density, smooth_density(&raw.graph, &raw.density, &smoothing).unwrap());
+        // fewer spurious peaks survive a tiny tau
+        assert!(out.tomato.modes.len() < raw.tomato.modes.len());
+        // tomato_cluster applies the same smoothing to the raw density
+        let direct = tomato_cluster(&raw.graph, &raw.density, TomatoParams::new(1e-9).with_smoothing(smoothing));
+        assert_eq!(direct.unwrap().cluster_of, out.tomato.cluster_of);
+    }
+}
+
+#[test]
+fn predictions_and_sweeps_use_the_smoothed_density() {
+    let pts = blobs();
+    let smoothing = Smoothing::new(SmoothingMethod::Mean, 1);
+    let model = TomatoModel::fit(BruteBackend::new(pts.clone()).unwrap(), params(Some(smoothing))).unwrap();
+    let b = BruteBackend::new(pts.clone()).unwrap();
+    assert_eq!(model.density(), &run_pipeline(&b, params(Some(smoothing))).unwrap().density[..]);
+
+    // the query is smoothed over its 8 graph neighbors with their fitted densities
+    let q = [0.1, 0.2];
+    let raw = density_at_point(&b, &params(None).density, &q).unwrap();
+    let mut dist: Vec<(usize, f64)> = pts
+        .iter()
+        .enumerate()
+        .map(|(j, p)| (j, (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2)))
+        .collect();
+    dist.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    let sum: f64 = dist[..8].iter().map(|&(j, _)| model.density()[j]).sum();
+    assert!((model.predict(&q).unwrap().density - (raw + sum) / 9.0).abs() < 1e-12);
+
+    let grid = SweepGrid::new(params(None).graph, params(None).density)
+        .with_smoothing(smoothing)
+        .with_tau(vec![SweepTau::Value(0.5)]);
+    let run = &sweep(&b, &grid).unwrap()[0];
+    let mut p = params(Some(smoothing));
+    p.tomato.tau = 0.5;
+    assert_eq!(run.n_clusters, run_pipeline(&b, p).unwrap().tomato.modes.len());
+}
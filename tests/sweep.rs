@@ -0,0 +1,156 @@
+use std::cell::Cell;
+
+use tomato::backend::{AnnBackend, BruteBackend};
+use tomato::metrics::NoiseHandling;
+use tomato::pipeline::{
+    rank_runs, run_pipeline, sweep, DensitySpec, GraphSpec, PipelineParams, SweepCriterion, SweepGrid, SweepTau,
+};
+use tomato::selection::TauCriterion;
+use tomato::{TomatoError, TomatoParams};
+
+struct CountingBackend {
+    inner: BruteBackend,
+    knn_calls: Cell<usize>,
+}
+
+impl AnnBackend for CountingBackend {
+    fn dim(&self) -> usize {
+        self.inner.dim()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn knn_indices_dist2(&self, query_index: usize, k: usize) -> Vec<(usize, f64)> {
+        self.knn_calls.set(self.knn_calls.get() + 1);
+        self.inner.knn_indices_dist2(query_index, k)
+    }
+
+    fn knn_point_dist2(&self, point: &[f64], k: usize) -> Result<Vec<(usize, f64)>, TomatoError> {
+        self.inner.knn_point_dist2(point, k)
+    }
+
+    fn range_dist2(&self, query_index: usize, radius2: f64) -> Vec<(usize, f64)> {
+        self.inner.range_dist2(query_index, radius2)
+    }
+}
+
+// Two blobs, even rows in the first one.
+fn points() -> Vec<Vec<f64>> {
+    (0..120)
+        .map(|i| {
+            let c = if i % 2 == 0 { 0.0 } else { 6.0 };
+            vec![c + (i as f64 * 0.7).sin(), c + (i as f64 * 1.3).cos()]
+        })
+        .collect()
+}
+
+fn grid() -> SweepGrid {
+    SweepGrid::new(
+        GraphSpec::Knn { k: 8, symmetrize: true },
+        DensitySpec::KdeGaussianKnn { k: 20, bandwidth2: 0.3 },
+    )
+    .with_graph_k(vec![5, 10])
+    .with_bandwidth2(vec![0.1, 0.5])
+    .with_tau(vec![
+        SweepTau::Select(TauCriterion::LargestGap),
+        SweepTau::Value(f64::INFINITY),
+    ])
+}
+
+#[test]
+fn sweep_matches_separate_pipeline_runs() {
+    let backend = BruteBackend::new(points()).unwrap();
+    let runs = sweep(&backend, &grid()).unwrap();
+    assert_eq!(runs.len(), 8);
+
+    let graphs: Vec<usize> = runs.iter().map(|r| r.graph.knn_k().unwrap()).collect();
+    assert_eq!(graphs, vec![5, 5, 5, 5, 10, 10, 10, 10]);
+
+    for run in &runs {
+        let res = run_pipeline(
+            &backend,
+            PipelineParams {
+                graph: run.graph.clone(),
+                density: run.density.clone(),
+                tomato: TomatoParams::new(run.tau),
+            },
+        )
+        .unwrap();
+        assert_eq!(run.n_clusters, res.tomato.modes.len());
+        assert_eq!(run.n_noise, 0);
+        assert!(run.gap > 0.0);
+        assert!(run.ari.is_none() && run.nmi.is_none() && run.silhouette.is_none());
+    }
+}
+
+#[test]
+fn sweep_queries_the_backend_once_per_point() {
+    let backend = CountingBackend {
+        inner: BruteBackend::new(points()).unwrap(),
+        knn_calls: Cell::new(0),
+    };
+    let runs = sweep(&backend, &grid().with_density_k(vec![15, 25])).unwrap();
+    assert_eq!(runs.len(), 16);
+    assert_eq!(backend.knn_calls.get(), backend.len());
+}
+
+#[test]
+fn runs_are_scored_and_ranked() {
+    let backend = BruteBackend::new(points()).unwrap();
+    let truth: Vec<usize> = (0..120).map(|i| i % 2).collect();
+    let mut runs = sweep(
+        &backend,
+        &grid()
+            .with_truth(truth, NoiseHandling::Exclude)
+            .with_silhouette(),
+    )
+    .unwrap();
+
+    for run in &runs {
+        assert!(run.ari.is_some() && run.nmi.is_some());
+        assert_eq!(run.silhouette.is_some(), run.n_clusters >= 2);
+    }
+
+    rank_runs(&mut runs, SweepCriterion::Ari);
+    assert!((runs[0].ari.unwrap() - 1.0).abs() < 1e-12);
+    assert!(runs.windows(2).all(|w| w[0].ari >= w[1].ari));
+
+    rank_runs(&mut runs, SweepCriterion::NClusters(2));
+    assert_eq!(runs[0].n_clusters, 2);
+
+    rank_runs(&mut runs, SweepCriterion::Silhouette);
+    assert!(runs[0].silhouette.unwrap() > 0.5);
+    assert!(runs.windows(2).all(|w| w[0].silhouette >= w[1].silhouette));
+
+    rank_runs(&mut runs, SweepCriterion::Gap);
+    assert!(runs.windows(2).all(|w| w[0].gap >= w[1].gap));
+}
+
+#[test]
+fn sweep_rejects_bad_grids_and_skips_impossible_taus() {
+    let backend = BruteBackend::new(points()).unwrap();
+
+    assert!(sweep(&backend, &grid().with_radius2(vec![1.0])).is_err());
+    let full = SweepGrid::new(
+        GraphSpec::RipsBrute { radius2: 1.0 },
+        DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.3 },
+    );
+    assert!(sweep(&backend, &full.clone().with_graph_k(vec![3])).is_err());
+    assert!(sweep(&backend, &full.clone().with_density_k(vec![3])).is_err());
+    assert!(matches!(
+        sweep(&backend, &grid().with_truth(vec![0; 3], NoiseHandling::Exclude)),
+        Err(TomatoError::InvalidLabels(_))
+    ));
+    assert!(matches!(
+        sweep(&backend, &grid().with_tau(vec![SweepTau::Value(-1.0)])),
+        Err(TomatoError::InvalidTau)
+    ));
+
+    let runs = sweep(&backend, &full.with_radius2(vec![0.5, 1.0, 2.0])).unwrap();
+    assert_eq!(runs.len(), 3);
+
+    let runs = sweep(&backend, &grid().with_tau(vec![SweepTau::Select(TauCriterion::NClusters(500))])).unwrap();
+    assert!(runs.is_empty());
+}
@@ -1,5 +1,5 @@
 use tomato::graph::Graph;
-use tomato::tomato::{tomato_cluster, TomatoParams};
+use tomato::tomato::{tomato_cluster, NoisePolicy, TomatoParams, NOISE};
 
 fn connected_components(adj: &[Vec<usize>]) -> Vec<usize> {
     let n = adj.len();
@@ -39,7 +39,7 @@ fn tau_huge_gives_graph_connected_components() {
     let g = Graph::new(adj.clone()).unwrap();
     let f = vec![5.0, 4.0, 3.0, 2.0, 1.0];
 
-    let res = tomato_cluster(&g, &f, TomatoParams { tau: 1e100 }).unwrap();
+    let res = tomato_cluster(&g, &f, TomatoParams::new(1e100)).unwrap();
 
     let cc = connected_components(&adj);
     for i in 0..f.len() {
@@ -68,8 +68,82 @@ fn batched_union_counterexample_regression() {
 
     let f = vec![4.0, 5.0, 10.0, 0.0];
     let tau = 4.5;
-    let res = tomato_cluster(&g, &f, TomatoParams { tau }).unwrap();
+    let res = tomato_cluster(&g, &f, TomatoParams::new(tau)).unwrap();
 
     assert_eq!(res.cluster_of[a], res.cluster_of[c]);
     assert_ne!(res.cluster_of[b], res.cluster_of[c]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn diagram_pairs_modes_with_saddles() {
+    let g = Graph::new(vec![
+        vec![3],
+        vec![3],
+        vec![3],
+        vec![1, 0, 2],
+    ])
+    .unwrap();
+
+    let f = vec![4.0, 5.0, 10.0, 0.0];
+    let res = tomato_cluster(&g, &f, TomatoParams::new(4.5)).unwrap();
+
+    let modes: Vec<usize> = res.diagram.iter().map(|p| p.mode).collect();
+    assert_eq!(modes, vec![2, 1, 0]);
+
+    assert!(res.diagram[0].is_essential());
+    assert_eq!(res.diagram[0].saddle, None);
+    assert_eq!(res.diagram[1].death, Some(0.0));
+    assert_eq!(res.diagram[1].saddle, Some(3));
+    assert_eq!(res.diagram[2].prominence(), 4.0);
+
+    let surviving = res.diagram.iter().filter(|p| p.prominence() >= 4.5).count();
+    assert_eq!(surviving, res.modes.len());
+}
+
+#[test]
+fn noise_policy_labels_unclustered_points() {
+    // 0-1-2 is a cluster with mode 0, 3-4 a cluster with mode 3, 5 an isolated point
+    let g = Graph::new(vec![
+        vec![1],
+        vec![0, 2],
+        vec![1],
+        vec![4],
+        vec![3],
+        vec![],
+    ])
+    .unwrap();
+    let f = vec![5.0, 4.0, 3.0, 2.0, 1.5, 0.1];
+
+    let res = tomato_cluster(&g, &f, TomatoParams::new(1.0)).unwrap();
+    assert_eq!(res.cluster_of[5], 5);
+    assert_eq!(res.n_noise(), 0);
+
+    let params = TomatoParams::new(1.0).with_noise(NoisePolicy {
+        min_density: Some(0.5),
+        ..Default::default()
+    });
+    let res = tomato_cluster(&g, &f, params).unwrap();
+    assert!(res.is_noise(5));
+    assert_eq!(res.modes, vec![0, 3]);
+
+    let params = TomatoParams::new(1.0).with_noise(NoisePolicy {
+        min_cluster_size: Some(3),
+        ..Default::default()
+    });
+    let res = tomato_cluster(&g, &f, params).unwrap();
+    assert_eq!(res.cluster_of, vec![0, 0, 0, NOISE, NOISE, NOISE]);
+    assert_eq!(res.modes, vec![0]);
+
+    let params = TomatoParams::new(1.0).with_noise(NoisePolicy {
+        min_prominence: Some(1.0e9),
+        ..Default::default()
+    });
+    let res = tomato_cluster(&g, &f, params).unwrap();
+    assert_eq!(res.n_noise(), 0);
+
+    let params = TomatoParams::new(1.0).with_noise(NoisePolicy {
+        min_prominence: Some(-1.0),
+        ..Default::default()
+    });
+    assert!(tomato_cluster(&g, &f, params).is_err());
+}
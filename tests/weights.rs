@@ -0,0 +1,196 @@
+use tomato::backend::BruteBackend;
+use tomato::pipeline::{
+    density_at_point, density_at_point_weighted, estimate_density, estimate_density_at_weighted, estimate_density_weighted,
+    run_pipeline, run_pipeline_with_weights, AdaptiveKde, DensitySpec, GraphSpec, Kernel, PipelineParams,
+    TomatoModel,
+};
+use tomato::stats::{weighted_zscore_in_place, zscore_in_place};
+use tomato::tomato::TomatoParams;
+use tomato::TomatoError;
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-10 * a.abs().max(1.0)
+}
+
+fn cloud() -> Vec<Vec<f64>> {
+    (0..120)
+        .map(|i| {
+            let t = i as f64;
+            vec![(t * 0.37).sin() * (1.0 + t / 60.0), (t * 0.91).cos()]
+        })
+        .collect()
+}
+
+fn specs() -> Vec<DensitySpec> {
+    vec![
+        DensitySpec::KnnLog { k: 6, eps: 0.0 },
+        DensitySpec::Dtm { k: 6, p: 2.0, eps: 0.0 },
+        DensitySpec::KdeGaussianKnn { k: 6, bandwidth2: 0.1 },
+        DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.1 },
+        DensitySpec::KdeKnn { kernel: Kernel::Epanechnikov, k: 6, bandwidth2: 0.3 },
+        DensitySpec::KdeRange { kernel: Kernel::Triweight, bandwidth2: 0.3 },
+        DensitySpec::KdeRange { kernel: Kernel::Cauchy, bandwidth2: 0.3 },
+        DensitySpec::KdeGaussianAdaptive { k: 6, bandwidth2: 0.1, method: AdaptiveKde::Balloon },
+        DensitySpec::KdeGaussianAdaptive { k: 6, bandwidth2: 0.1, method: AdaptiveKde::SamplePoint },
+    ]
+}
+
+#[test]
+fn unit_weights_give_the_unweighted_density() {
+    let b = BruteBackend::new(cloud()).unwrap();
+    let ones = vec![1.0; 120];
+    for spec in specs() {
+        let plain = estimate_density(&b, spec.clone()).unwrap();
+        let weighted = estimate_density_weighted(&b, spec.clone(), &ones).unwrap();
+        for i in 0..plain.len() {
+            assert!(close(plain[i], weighted[i]), "{:?} at {}", spec, i);
+        }
+        let q = [0.3, -0.2];
+        let a = density_at_point(&b, &spec, &q).unwrap();
+        let w = density_at_point_weighted(&b, &spec, &q, &ones).unwrap();
+        assert!(close(a, w), "{:?}", spec);
+        let batch = estimate_density_at_weighted(&b, &spec, &[q.to_vec(), vec![1.0, 0.5]], &ones).unwrap();
+        assert_eq!(batch[0], w);
+        assert_eq!(batch[1], density_at_point_weighted(&b, &spec, &[1.0, 0.5], &ones).unwrap());
+    }
+}
+
+#[test]
+fn integer_weights_match_duplicated_rows_for_kernel_sums() {
+    let pts = cloud();
+    let mut weights = vec![1.0; pts.len()];
+    let mut dup = pts.clone();
+    for j in (0..pts.len()).step_by(7) {
+        weights[j] = 3.0;
+        dup.push(pts[j].clone());
+        dup.push(pts[j].clone());
+    }
+    let b = BruteBackend::new(pts.clone()).unwrap();
+    let bd = BruteBackend::new(dup).unwrap();
+
+    for spec in [
+        DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.1 },
+        DensitySpec::KdeRange { kernel: Kernel::Tophat, bandwidth2: 0.3 },
+        DensitySpec::KdeRange { kernel: Kernel::Laplacian, bandwidth2: 0.3 },
+    ] {
+        let weighted = estimate_density_weighted(&b, spec.clone(), &weights).unwrap();
+        let copies = estimate_density(&bd, spec.clone()).unwrap();
+        // copies of a point count toward each other, so compare at the points left single
+        for i in (0..pts.len()).filter(|i| i % 7 != 0) {
+            assert!(close(weighted[i], copies[i]), "{:?} at {}", spec, i);
+        }
+    }
+}
+
+#[test]
+fn zero_weight_points_do_not_count() {
+    let b = BruteBackend::new(vec![vec![0.0], vec![0.5], vec![1.0]]).unwrap();
+    let spec = DensitySpec::KdeRange { kernel: Kernel::Tophat, bandwidth2: 0.36 };
+    let d = estimate_density_weighted(&b, spec, &[1.0, 0.0, 2.0]).unwrap();
+    assert_eq!(d, vec![0.0, 3.0, 0.0]);
+}
+
+#[test]
+fn log_estimators_reject_neighborhoods_without_mass() {
+    let b = BruteBackend::new((0..6).map(|i| vec![i as f64]).collect()).unwrap();
+    let w = [1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+    for spec in [DensitySpec::KnnLog { k: 2, eps: 0.0 }, DensitySpec::Dtm { k: 2, p: 2.0, eps: 0.0 }] {
+        // point 0 has neighbors 1 and 2, both of weight 0
+        match estimate_density_weighted(&b, spec.clone(), &w) {
+            Err(TomatoError::InvalidWeights(msg)) => assert!(msg.contains("point 0"), "{}", msg),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            density_at_point_weighted(&b, &spec, &[1.4], &w),
+            Err(TomatoError::InvalidWeights(_))
+        ));
+        assert!(density_at_point_weighted(&b, &spec, &[3.2], &w).unwrap().is_finite());
+    }
+    // kernel sums give such a point density 0
+    let kde = estimate_density_weighted(&b, DensitySpec::KdeGaussianKnn { k: 2, bandwidth2: 0.5 }, &w).unwrap();
+    assert_eq!(kde[0], 0.0);
+}
+
+#[test]
+fn invalid_weights_are_rejected() {
+    let b = BruteBackend::new(cloud()).unwrap();
+    let spec = DensitySpec::KnnLog { k: 4, eps: 0.0 };
+    let short = estimate_density_weighted(&b, spec.clone(), &[1.0; 3]);
+    assert!(matches!(short, Err(TomatoError::InvalidWeights(_))));
+    let mut w = vec![1.0; 120];
+    w[5] = -1.0;
+    assert!(matches!(estimate_density_weighted(&b, spec.clone(), &w), Err(TomatoError::InvalidWeights(_))));
+    w[5] = f64::NAN;
+    assert!(matches!(estimate_density_weighted(&b, spec, &w), Err(TomatoError::InvalidWeights(_))));
+
+    let params = PipelineParams {
+        graph: GraphSpec::RipsBrute { radius2: 0.1 },
+        density: DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.1 },
+        tomato: TomatoParams::new(0.5),
+    };
+    let res = run_pipeline_with_weights(&b, params, Some(&[1.0; 119]));
+    assert!(matches!(res, Err(TomatoError::InvalidWeights(_))));
+}
+
+#[test]
+fn weighted_zscore_matches_duplicated_rows() {
+    let pts = cloud();
+    let weights: Vec<f64> = (0..pts.len()).map(|i| (i % 3) as f64).collect();
+    let mut dup = Vec::new();
+    for (p, &w) in pts.iter().zip(&weights) {
+        for _ in 0..w as usize {
+            dup.push(p.clone());
+        }
+    }
+    let mut weighted = pts.clone();
+    weighted_zscore_in_place(&mut weighted, &weights).unwrap();
+    zscore_in_place(&mut dup);
+
+    let mut k = 0;
+    for (i, &w) in weights.iter().enumerate() {
+        for _ in 0..w as usize {
+            for j in 0..2 {
+                assert!(close(weighted[i][j], dup[k][j]));
+            }
+            k += 1;
+        }
+    }
+
+    let mut zero = pts;
+    let res = weighted_zscore_in_place(&mut zero, &vec![0.0; 120]);
+    assert!(matches!(res, Err(TomatoError::InvalidWeights(_))));
+}
+
+#[test]
+fn weights_scale_the_density_of_the_pipeline_and_model() {
+    let mut pts = Vec::new();
+    for i in 0..20 {
+        let t = (i as f64 * 0.7).sin() * 0.3;
+        pts.push(vec![t, 0.0]);
+        pts.push(vec![5.0 + t, 0.0]);
+    }
+    let params = PipelineParams {
+        graph: GraphSpec::RipsBrute { radius2: 0.1 },
+        density: DensitySpec::KdeGaussianFullBrute { bandwidth2: 0.05 },
+        tomato: TomatoParams::new(1e-9),
+    };
+    let b = BruteBackend::new(pts.clone()).unwrap();
+    let plain = run_pipeline(&b, params.clone()).unwrap();
+    let ones = run_pipeline_with_weights(&b, params.clone(), Some(&[1.0; 40])).unwrap();
+    assert_eq!(plain.tomato.cluster_of, ones.tomato.cluster_of);
+
+    let weights: Vec<f64> = (0..40).map(|i| if i % 2 == 1 { 4.0 } else { 1.0 }).collect();
+    let heavy = run_pipeline_with_weights(&b, params.clone(), Some(&weights)).unwrap();
+    for i in 0..40 {
+        let scale = if i % 2 == 1 { 4.0 } else { 1.0 };
+        assert!(close(heavy.density[i], scale * plain.density[i]));
+    }
+
+    let model = TomatoModel::fit_with_weights(b, params, Some(weights.clone())).unwrap();
+    assert_eq!(model.weights(), Some(&weights[..]));
+    assert_eq!(model.density(), &heavy.density[..]);
+    let pred = model.predict(&[5.05, 0.0]).unwrap();
+    assert_eq!(pred.label, model.labels()[1]);
+    let q = density_at_point_weighted(model.backend(), &model.params().density, &[5.05, 0.0], &weights).unwrap();
+    assert_eq!(pred.density, q);
+}